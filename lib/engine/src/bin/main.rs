@@ -2,12 +2,22 @@
 #![feature(lazy_cell)]
 extern crate engine;
 
-use engine::engine::UCCIEngine;
+use engine::engine::{EngineArgs, UCCIEngine};
+use std::{env, process};
 
-fn main() {
+fn main() -> std::io::Result<()> {
     let p = module_path!();
 
     dbg!(p);
 
-    UCCIEngine::new(include_str!("../../BOOK.DAT").into()).start();
+    // 命令行参数预设搜索深度、置换表大小和线程数，省得每次都发setoption
+    let args = EngineArgs::parse(env::args().skip(1)).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        eprintln!("用法: engine [--depth N] [--hash MB] [--threads N]");
+        process::exit(2);
+    });
+
+    UCCIEngine::new(include_str!("../../BOOK.DAT").into())
+        .with_args(&args)
+        .start()
 }
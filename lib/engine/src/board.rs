@@ -1,13 +1,35 @@
-use std::vec;
+use std::{
+    collections::HashMap,
+    fmt,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+    vec,
+};
+
+use getrandom::getrandom;
 
-use crate::constant::{
-    FEN_MAP, KILL, MAX, MAX_DEPTH, MIN, RECORD_SIZE, ZOBRIST_TABLE, ZOBRIST_TABLE_LOCK,
+use crate::{
+    constant::{
+        FEN_MAP, KILL, MAX, MAX_DEPTH, MIN, RECORD_SIZE, ZOBRIST_TABLE, ZOBRIST_TABLE_LOCK,
+    },
+    difficulty::Difficulty,
+    eval::{default_evaluator, eval_breakdown, EvalBreakdown, EvalParams, Evaluator},
+    record::{Record, RecordFlag, TranspositionTable},
+    zobrist::next_seeded,
 };
 
 pub const BOARD_WIDTH: i32 = 9;
 pub const BOARD_HEIGHT: i32 = 10;
+// 棋盘格子数，按行优先编号，见Position::to_index
+pub const BOARD_SQUARES: usize = (BOARD_WIDTH * BOARD_HEIGHT) as usize;
 
 #[derive(Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Chess {
     Black(ChessType),
     Red(ChessType),
@@ -22,6 +44,13 @@ impl Chess {
             0x0
         }
     }
+    pub fn see_value(&self) -> i32 {
+        if let Some(ct) = self.chess_type() {
+            ct.see_value()
+        } else {
+            0
+        }
+    }
     pub fn belong_to(&self, player: Player) -> bool {
         if let Chess::Black(_) = self {
             player == Player::Black
@@ -45,9 +74,18 @@ impl Chess {
             Chess::None => None,
         }
     }
+    // 换成对方的同类棋子
+    pub fn swap_color(&self) -> Chess {
+        match self {
+            Chess::Black(ct) => Chess::Red(*ct),
+            Chess::Red(ct) => Chess::Black(*ct),
+            Chess::None => Chess::None,
+        }
+    }
 }
 
 #[derive(Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ChessType {
     King,    // 帅
     Advisor, // 士
@@ -81,6 +119,30 @@ impl ChessType {
             ChessType::Pawn => 2,
         }
     }
+    // 静态评价的安全边际，不吃子时该棋子位置分的最大变化
+    pub fn futility_margin(&self) -> i32 {
+        match self {
+            ChessType::King => 15,
+            ChessType::Advisor => 5,
+            ChessType::Bishop => 5,
+            ChessType::Knight => 30,
+            ChessType::Rook => 40,
+            ChessType::Cannon => 15,
+            ChessType::Pawn => 45,
+        }
+    }
+    // 静态交换评估用的子力价值，帅的价值足够大，保证不会被换掉
+    pub fn see_value(&self) -> i32 {
+        match self {
+            ChessType::King => 1000,
+            ChessType::Advisor => 20,
+            ChessType::Bishop => 20,
+            ChessType::Knight => 40,
+            ChessType::Rook => 90,
+            ChessType::Cannon => 45,
+            ChessType::Pawn => 10,
+        }
+    }
     pub fn move_value(&self) -> i32 {
         match self {
             ChessType::King => 1,
@@ -95,6 +157,7 @@ impl ChessType {
 }
 
 #[derive(Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Player {
     Red,
     Black,
@@ -118,6 +181,7 @@ impl Player {
 }
 
 #[derive(Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Position {
     pub row: i32,
     pub col: i32,
@@ -139,12 +203,29 @@ impl Position {
     pub fn right(&self, delta: i32) -> Self {
         Position::new(self.row, self.col + delta)
     }
+    // 移动dr行dc列，出了棋盘返回None；up/down/left/right不检查边界，结果要用in_board判断
+    pub fn offset(&self, dr: i32, dc: i32) -> Option<Self> {
+        let pos = Position::new(self.row + dr, self.col + dc);
+        if in_board(pos) {
+            Some(pos)
+        } else {
+            None
+        }
+    }
     pub fn flip(&self) -> Self {
         Position::new(BOARD_HEIGHT - 1 - self.row, BOARD_WIDTH - 1 - self.col)
     }
+    // 格子编号，从左上角开始按行数，范围是0..BOARD_SQUARES
+    pub fn to_index(&self) -> usize {
+        (self.row * BOARD_WIDTH + self.col) as usize
+    }
+    pub fn from_index(index: usize) -> Self {
+        Position::new(index as i32 / BOARD_WIDTH, index as i32 % BOARD_WIDTH)
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Move {
     pub player: Player, // 玩家
     pub from: Position, // 起手位置
@@ -176,6 +257,19 @@ impl Move {
     }
 }
 
+// 坐标记法，如b0c2
+impl fmt::Display for Move {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}{}",
+            self.from
+                .to_string(),
+            self.to.to_string()
+        )
+    }
+}
+
 impl From<&str> for Position {
     fn from(m: &str) -> Self {
         let mb = m.as_bytes();
@@ -185,6 +279,106 @@ impl From<&str> for Position {
         )
     }
 }
+
+// 坐标字符串解析失败的原因
+#[derive(Debug, PartialEq)]
+pub enum PositionParseError {
+    Length(usize), // 坐标应该是两个字符
+    File(char),    // 列不在a到i之间
+    Rank(char),    // 行不在0到9之间
+}
+
+impl fmt::Display for PositionParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PositionParseError::Length(len) => write!(f, "坐标应为2个字符，实际为{}个", len),
+            PositionParseError::File(c) => write!(f, "列{}不在a到i之间", c),
+            PositionParseError::Rank(c) => write!(f, "行{}不在0到9之间", c),
+        }
+    }
+}
+
+impl std::error::Error for PositionParseError {}
+
+#[derive(Debug, PartialEq)]
+pub enum FenParseError {
+    Rows(usize),             // 棋盘应该是10行
+    Columns(usize, i32),     // 第几行的列数不是9
+    Chess(char),             // 不认识的棋子
+    Turn(String),            // 走棋方不是w、r或b
+    King,                    // 双方没有各一个将帅
+    Position(PositionError), // 棋子的摆法在对局中不可能出现
+}
+
+impl fmt::Display for FenParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FenParseError::Rows(rows) => write!(f, "棋盘应为10行，实际为{}行", rows),
+            FenParseError::Columns(row, cols) => {
+                write!(f, "第{}行应为9列，实际为{}列", row + 1, cols)
+            }
+            FenParseError::Chess(c) => write!(f, "不认识的棋子{}", c),
+            FenParseError::Turn(turn) => write!(f, "走棋方应为w或b，实际为{}", turn),
+            FenParseError::King => write!(f, "双方应各有一个将帅"),
+            FenParseError::Position(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for FenParseError {}
+
+#[derive(Debug, PartialEq)]
+pub enum PositionError {
+    KingCount(Player, usize),  // 一方的将帅不是一个
+    KingOutsidePalace(Player), // 将帅不在九宫格内
+    PawnPosition(Position),    // 兵卒在走不到的位置
+    OpponentInCheck,           // 不走棋的一方正被将军
+}
+
+impl fmt::Display for PositionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let side = |player: &Player| {
+            if *player == Player::Red {
+                "红方"
+            } else {
+                "黑方"
+            }
+        };
+        match self {
+            PositionError::KingCount(player, count) => {
+                write!(f, "{}应有一个将帅，实际有{}个", side(player), count)
+            }
+            PositionError::KingOutsidePalace(player) => {
+                write!(f, "{}的将帅不在九宫格内", side(player))
+            }
+            PositionError::PawnPosition(pos) => write!(f, "{}的兵卒不可能走到", pos.to_string()),
+            PositionError::OpponentInCheck => write!(f, "不走棋的一方正被将军"),
+        }
+    }
+}
+
+impl std::error::Error for PositionError {}
+
+// 校验坐标的解析，用于外部输入，内部可信的坐标仍然可以用From
+// 有了From<&str>就不能再实现TryFrom<&str>，所以用FromStr，通过"a0".parse()调用
+impl FromStr for Position {
+    type Err = PositionParseError;
+    fn from_str(m: &str) -> Result<Self, Self::Err> {
+        let mut chars = m.chars();
+        match (chars.next(), chars.next(), chars.next()) {
+            (Some(file), Some(rank), None) => {
+                if !('a'..='i').contains(&file) {
+                    return Err(PositionParseError::File(file));
+                }
+                if !rank.is_ascii_digit() {
+                    return Err(PositionParseError::Rank(rank));
+                }
+                Ok(Position::from(m))
+            }
+            _ => Err(PositionParseError::Length(m.chars().count())),
+        }
+    }
+}
 impl ToString for Position {
     fn to_string(&self) -> String {
         format!(
@@ -195,27 +389,202 @@ impl ToString for Position {
     }
 }
 
+// 对局状态
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum GameStatus {
+    Playing,
+    RedWin,
+    BlackWin,
+    Draw,
+}
+
+impl GameStatus {
+    // 棋谱里的对局结果，红方先走，红胜"1-0"，黑胜"0-1"，和棋"1/2-1/2"，没有结束"*"
+    pub fn result_token(&self) -> &'static str {
+        match self {
+            GameStatus::Playing => "*",
+            GameStatus::RedWin => "1-0",
+            GameStatus::BlackWin => "0-1",
+            GameStatus::Draw => "1/2-1/2",
+        }
+    }
+}
+
+// 着法的类型，排序和剪枝时区分吃子、将军和普通着法
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum MoveKind {
+    Quiet,
+    Capture,
+    Check,
+    CaptureCheck,
+}
+
+impl MoveKind {
+    fn new(capture: bool, check: bool) -> Self {
+        match (capture, check) {
+            (false, false) => MoveKind::Quiet,
+            (true, false) => MoveKind::Capture,
+            (false, true) => MoveKind::Check,
+            (true, true) => MoveKind::CaptureCheck,
+        }
+    }
+    pub fn is_capture(&self) -> bool {
+        matches!(self, MoveKind::Capture | MoveKind::CaptureCheck)
+    }
+    pub fn is_check(&self) -> bool {
+        matches!(self, MoveKind::Check | MoveKind::CaptureCheck)
+    }
+}
+
+// 迭代加深的搜索结果和搜索量统计
 #[derive(Clone, Debug)]
-pub struct Record {
-    pub value: i32,
-    pub depth: i32,
+pub struct SearchResult {
+    pub score: i32,
     pub best_move: Option<Move>,
-    pub zobrist_lock: u64,
-    pub turn: Player,
+    pub pv: Vec<Move>,      // 主要变例，从最佳着法开始
+    pub nodes: u64,         // 搜索过的节点数，包括静态搜索
+    pub gen_nodes: u64,     // 生成着法的次数
+    pub depth_reached: i32, // 完整搜索完的最大深度，被中止的那一层不算
+    pub elapsed: Duration,
+}
+
+// 搜索参数
+#[derive(Clone, Debug)]
+pub struct SearchConfig {
+    pub futility_pruning: bool,      // 前沿节点的无用剪枝
+    pub mate_distance_pruning: bool, // 杀棋步数剪枝
+    pub check_extension: i32,        // 被将军时延伸的深度，0表示不延伸
+    pub quies_max_ply: i32,          // 静态搜索最多再走多少步
+    pub node_limit: Option<u64>,     // 一次搜索的节点数上限，达到后和停止标志一样中止搜索
+    pub singular_extension: bool,    // 置换表着法明显好于其他着法时延伸一层
+    pub contempt: i32,               // 和棋对根节点走棋方的减分，正数表示不愿意和棋
+    pub max_distance: i32,           // 离根节点的步数达到这个值时不再搜索，按和棋处理
+    pub razoring: bool,              // 浅层节点的剃刀剪枝
+    pub delta_pruning: bool,         // 静态搜索里吃子也追不上alpha时不搜索
+    pub quies_see: bool,             // 静态搜索里用大子吃有保护的小子、交换下来亏子时不搜索
+    pub null_move: bool,             // 空着裁剪，减少的深度随剩余深度增加
+    pub null_move_verify: bool,      // 深度较大时空着裁剪前用正常搜索验证，避免等着局面出错
+    pub staged_moves: bool,          // 分阶段生成着法，先吃子后其他着法
+    pub counter_moves: bool,         // 对方上一步着法的应着排在其他不吃子的着法前面
+    pub quies_checks: bool,          // 静态搜索第一层也搜索不吃子的将军着法
+    // 迭代加深时根节点每个着法的分数加上[-root_jitter, root_jitter]内的随机数，
+    // 分数接近的着法每盘棋选得不一样；只应该设成很小的值，0表示不加
+    pub root_jitter: i32,
+    pub jitter_seed: u64, // 随机数的种子，种子相同时每个着法加的分数相同
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        SearchConfig {
+            futility_pruning: true,
+            mate_distance_pruning: true,
+            check_extension: 1,
+            quies_max_ply: 16,
+            node_limit: None,
+            singular_extension: true,
+            contempt: 0,
+            max_distance: 2 * MAX_DEPTH,
+            razoring: true,
+            delta_pruning: true,
+            quies_see: true,
+            null_move: true,
+            null_move_verify: true,
+            staged_moves: true,
+            counter_moves: true,
+            quies_checks: false,
+            root_jitter: 0,
+            jitter_seed: 0,
+        }
+    }
 }
 
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Board {
     // 9×10的棋盘，红方在下，黑方在上
     pub chesses: [[Chess; BOARD_WIDTH as usize]; BOARD_HEIGHT as usize],
+    // 双方每种棋子的数量之和，按ChessType::value()编号，set_chess时增减，对局阶段直接由它算出
+    #[cfg_attr(feature = "serde", serde(skip))]
+    piece_counts: [i32; 7],
     pub turn: Player,
     pub counter: i32,
+    pub nodes: u64, // 搜索过的节点数，包括静态搜索
     pub gen_counter: i32,
+    pub sort_counter: u64, // 排序过的着法数
     pub move_history: Vec<Move>,
+    pub redo_stack: Vec<Move>, // 悔棋后可以重做的着法
+    #[cfg_attr(feature = "serde", serde(skip))]
     pub best_moves_last: Vec<Move>,
-    pub records: Vec<Option<Record>>,
+    // 置换表，克隆出的棋盘共享同一张表
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub records: Arc<TranspositionTable>,
+    // zobrist表每次启动随机生成，不能保存，加载时重新计算
+    #[cfg_attr(feature = "serde", serde(skip))]
     pub zobrist_value: u64,
+    #[cfg_attr(feature = "serde", serde(skip))]
     pub zobrist_value_lock: u64,
     pub distance: i32,
+    // FEN里的半回合计数（距上次吃子的步数）和回合数，之后走的棋由move_history推算
+    pub halfmove_clock: i32,
+    pub fullmove_number: i32,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub config: SearchConfig,
+    // 评价参数，克隆出的棋盘共享同一份
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub eval_params: Arc<EvalParams>,
+    // 评价函数，默认是子力加位置分，可以换成自己实现的，克隆出的棋盘共享同一个
+    #[cfg_attr(feature = "serde", serde(skip, default = "default_evaluator"))]
+    pub evaluator: Arc<dyn Evaluator>,
+    // 停止搜索的标志，设置后搜索尽快返回，结果作废
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub stop: Arc<AtomicBool>,
+    // 下一次iterative_deepening的截止时间，搜索中每隔TIME_CHECK_NODES个节点检查一次，
+    // 超时后中止当前这一层；只对一次搜索有效，搜索结束后清除
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub deadline: Option<Instant>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    time_up: bool,
+    // 迭代加深时根节点的distance，只在这一层给着法加随机分数
+    #[cfg_attr(feature = "serde", serde(skip))]
+    jitter_root: Option<i32>,
+    // 本次搜索根节点的distance；distance是开局以来的步数，离根节点的步数要减去它
+    #[cfg_attr(feature = "serde", serde(skip))]
+    root_distance: i32,
+    // 本次搜索开始时的节点数，nodes是累计的，node_limit从这里算起
+    #[cfg_attr(feature = "serde", serde(skip))]
+    start_nodes: u64,
+    // 搜索时每一层复用的着法缓冲区，避免每个节点重新分配
+    #[cfg_attr(feature = "serde", serde(skip))]
+    move_buffers: Vec<Vec<Move>>,
+    // 当前局面之前走过的局面出现的次数，do_move时加上离开的局面，undo_move时减去
+    #[cfg_attr(feature = "serde", serde(skip))]
+    repetitions: HashMap<(u64, u64, bool), u32>,
+    // 历史表，不吃子的着法发生beta截断时按(棋子, 落点)累加分数，着法排序时参考
+    #[cfg_attr(feature = "serde", serde(skip))]
+    history_table: Vec<i32>,
+    // 应着表，不吃子的着法发生beta截断时按对方上一步的(起点, 落点)记下这个着法
+    #[cfg_attr(feature = "serde", serde(skip))]
+    counter_move_table: Vec<Option<Move>>,
+    // 当前走棋方是否被将军，apply_move时重新计算，undo_move时从check_history恢复
+    #[cfg_attr(feature = "serde", serde(skip))]
+    checked: bool,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    check_history: Vec<bool>,
+    // 下一个节点不能走空着，刚走过空着或者在做验证搜索，节点开始时读取并清除
+    #[cfg_attr(feature = "serde", serde(skip))]
+    skip_null: bool,
+}
+
+// 杀棋分数离将死还有几步（单方的一步算一步），正数是走棋方将死对方，负数是走棋方被将死，
+// 不是杀棋分数时返回None；杀棋分数按离搜索根节点的步数计算，和对局已经走了多少步无关
+pub fn mate_distance(value: i32) -> Option<i32> {
+    if value > -KILL {
+        Some(MAX - value)
+    } else if value < KILL {
+        Some(MIN - value)
+    } else {
+        None
+    }
 }
 
 // 棋子是否在棋盘内
@@ -242,100 +611,134 @@ pub fn in_palace(pos: Position, player: Player) -> bool {
     }
 }
 
-const KING_VALUE_TABLE: [[i32; BOARD_WIDTH as usize]; BOARD_HEIGHT as usize] = [
-    [0, 0, 0, 0, 0, 0, 0, 0, 0],
-    [0, 0, 0, 0, 0, 0, 0, 0, 0],
-    [0, 0, 0, 0, 0, 0, 0, 0, 0],
-    [0, 0, 0, 0, 0, 0, 0, 0, 0],
-    [0, 0, 0, 0, 0, 0, 0, 0, 0],
-    [0, 0, 0, 0, 0, 0, 0, 0, 0],
-    [0, 0, 0, 0, 0, 0, 0, 0, 0],
-    [0, 0, 0, 1, 1, 1, 0, 0, 0],
-    [0, 0, 0, 2, 2, 2, 0, 0, 0],
-    [0, 0, 0, 11, 15, 11, 0, 0, 0],
-];
-
-const ADVISOR_VALUE_TABLE: [[i32; BOARD_WIDTH as usize]; BOARD_HEIGHT as usize] = [
-    [0, 0, 0, 0, 0, 0, 0, 0, 0],
-    [0, 0, 0, 0, 0, 0, 0, 0, 0],
-    [0, 0, 0, 0, 0, 0, 0, 0, 0],
-    [0, 0, 0, 0, 0, 0, 0, 0, 0],
-    [0, 0, 0, 0, 0, 0, 0, 0, 0],
-    [0, 0, 0, 0, 0, 0, 0, 0, 0],
-    [0, 0, 0, 0, 0, 0, 0, 0, 0],
-    [0, 0, 0, 20, 0, 20, 0, 0, 0],
-    [0, 0, 0, 0, 23, 0, 0, 0, 0],
-    [0, 0, 0, 20, 0, 20, 0, 0, 0],
-];
-
-const BISHOP_VALUE_TABLE: [[i32; BOARD_WIDTH as usize]; BOARD_HEIGHT as usize] = [
-    [0, 0, 0, 0, 0, 0, 0, 0, 0],
-    [0, 0, 0, 0, 0, 0, 0, 0, 0],
-    [0, 0, 0, 0, 0, 0, 0, 0, 0],
-    [0, 0, 0, 0, 0, 0, 0, 0, 0],
-    [0, 0, 0, 0, 0, 0, 0, 0, 0],
-    [0, 0, 20, 0, 0, 0, 20, 0, 0],
-    [0, 0, 0, 0, 0, 0, 0, 0, 0],
-    [18, 0, 0, 0, 23, 0, 0, 0, 18],
-    [0, 0, 0, 0, 0, 0, 0, 0, 0],
-    [0, 0, 20, 0, 0, 0, 20, 0, 0],
-];
-
-const ROOK_VALUE_TABLE: [[i32; BOARD_WIDTH as usize]; BOARD_HEIGHT as usize] = [
-    [206, 208, 207, 213, 214, 213, 207, 208, 206],
-    [206, 212, 209, 216, 233, 216, 209, 212, 206],
-    [206, 208, 207, 214, 216, 214, 207, 208, 206],
-    [206, 213, 213, 216, 216, 216, 213, 213, 206],
-    [208, 211, 211, 214, 215, 214, 211, 211, 208],
-    [208, 212, 212, 214, 215, 214, 212, 212, 208],
-    [204, 209, 204, 212, 214, 212, 204, 209, 204],
-    [198, 208, 204, 212, 212, 212, 204, 208, 198],
-    [200, 208, 206, 212, 200, 212, 206, 208, 200],
-    [194, 206, 204, 212, 200, 212, 204, 206, 194],
-];
-
-const KNIGHT_VALUE_TABLE: [[i32; BOARD_WIDTH as usize]; BOARD_HEIGHT as usize] = [
-    [90, 90, 90, 96, 90, 96, 90, 90, 90],
-    [90, 96, 103, 97, 94, 97, 103, 96, 90],
-    [92, 98, 99, 103, 99, 103, 99, 98, 92],
-    [93, 108, 100, 107, 100, 107, 100, 108, 93],
-    [90, 100, 99, 103, 104, 103, 99, 100, 90],
-    [90, 98, 101, 102, 103, 102, 101, 98, 90],
-    [92, 94, 98, 95, 98, 95, 98, 94, 92],
-    [93, 92, 94, 95, 92, 95, 94, 92, 93],
-    [85, 90, 92, 93, 78, 93, 92, 90, 85],
-    [88, 85, 90, 88, 90, 88, 90, 85, 88],
-];
-
-const CANNON_VALUE_TABLE: [[i32; BOARD_WIDTH as usize]; BOARD_HEIGHT as usize] = [
-    [100, 100, 96, 91, 90, 91, 96, 100, 100],
-    [98, 98, 96, 92, 89, 92, 96, 98, 98],
-    [97, 97, 96, 91, 92, 91, 96, 97, 97],
-    [96, 99, 99, 98, 100, 98, 99, 99, 96],
-    [96, 96, 96, 96, 100, 96, 96, 96, 96],
-    [95, 96, 99, 96, 100, 96, 99, 96, 95],
-    [96, 96, 96, 96, 96, 96, 96, 96, 96],
-    [97, 96, 100, 99, 101, 99, 100, 96, 97],
-    [96, 97, 98, 98, 98, 98, 98, 97, 96],
-    [96, 96, 97, 99, 99, 99, 97, 96, 96],
-];
-
-const PAWN_VALUE_TABLE: [[i32; BOARD_WIDTH as usize]; BOARD_HEIGHT as usize] = [
-    [9, 9, 9, 11, 13, 11, 9, 9, 9],
-    [19, 24, 34, 42, 44, 42, 34, 24, 19],
-    [19, 24, 32, 37, 37, 37, 32, 24, 19],
-    [19, 23, 27, 29, 30, 29, 27, 23, 19],
-    [14, 18, 20, 27, 29, 27, 20, 18, 14],
-    [7, 0, 13, 0, 16, 0, 13, 0, 7],
-    [7, 0, 7, 0, 15, 0, 7, 0, 7],
-    [0, 0, 0, 0, 0, 0, 0, 0, 0],
-    [0, 0, 0, 0, 0, 0, 0, 0, 0],
-    [0, 0, 0, 0, 0, 0, 0, 0, 0],
-];
-
-const INITIATIVE_BONUS: i32 = 3;
-
-const RECORD_NONE: Option<Record> = None;
+// 对局阶段的刻度，开局时为TOTAL_PHASE
+pub(crate) const TOTAL_PHASE: i32 = 48;
+// 开局时双方车马炮的子力之和
+const OPENING_PHASE_MATERIAL: i32 = 700;
+
+// 着法排序时将军着法的加分，排在不亏子的吃子之后，普通着法之前
+const CHECK_BONUS: i32 = 5;
+
+// 历史表按(走棋方, 棋子类型, 落点)记录，每一项不超过HISTORY_MAX，避免不停累加后溢出
+const HISTORY_SIZE: usize = 2 * 7 * BOARD_SQUARES;
+const HISTORY_MAX: i32 = 1 << 20;
+
+// 应着表按对方上一步的(起点, 落点)记录；应着的加分让它排在不将军的普通着法前面
+const COUNTER_MOVE_SIZE: usize = BOARD_SQUARES * BOARD_SQUARES;
+const COUNTER_MOVE_BONUS: i32 = 4;
+
+// 奇异延伸：剩余深度不小于SINGULAR_MIN_DEPTH时才检查，
+// 其他着法都达不到置换表分数减去SINGULAR_MARGIN，才认为置换表着法是唯一的好着法
+const SINGULAR_MIN_DEPTH: i32 = 4;
+const SINGULAR_MARGIN: i32 = 20;
+
+// 剃刀剪枝：剩余深度不超过RAZOR_DEPTH时，局面分加上每层RAZOR_MARGIN仍不到alpha，
+// 就只做静态搜索
+const RAZOR_DEPTH: i32 = 2;
+const RAZOR_MARGIN: i32 = 30;
+
+// 静态搜索的delta剪枝：局面分加上被吃的子在评价里的分数和DELTA_MARGIN仍不到alpha的吃子不搜索
+const DELTA_MARGIN: i32 = 50;
+
+// 空着裁剪：减少NULL_MOVE_REDUCTION加上剩余深度的六分之一，
+// 剩余深度不小于NULL_MOVE_VERIFY_DEPTH时空着的结果要用正常搜索验证
+const NULL_MOVE_REDUCTION: i32 = 2;
+const NULL_MOVE_VERIFY_DEPTH: i32 = 6;
+
+// 限时搜索时每搜索这么多个节点看一次时间，取2的幂，用位与代替取余
+const TIME_CHECK_NODES: u64 = 1024;
+
+// 着法分阶段生成：先走置换表和上次迭代主要变例里的着法，再生成吃子，最后生成其他着法，
+// 前面的着法发生截断时，后面的阶段不用再生成和排序
+#[derive(Clone, Copy, PartialEq)]
+enum PickStage {
+    Priority,
+    Captures,
+    Quiets,
+    All, // 不分阶段时，吃子和其他着法一起生成
+    Done,
+}
+
+struct MovePicker {
+    stage: PickStage,
+    priority: Vec<Move>, // 优先搜索的着法，之后的阶段跳过
+    moves: Vec<Move>,    // 当前阶段的着法
+    index: usize,
+    sort: bool,
+}
+
+impl MovePicker {
+    // 优先的着法要确认是当前局面走棋方能走的，避免哈希冲突或者置换表里另一方的着法
+    fn new(board: &mut Board, mut priority: Vec<Move>, sort: bool) -> Self {
+        let turn = board.turn;
+        priority.retain(|m| board.is_valid_move_for(m, turn));
+        let mut moves = board.take_move_buffer();
+        moves.clear();
+        moves.extend_from_slice(&priority);
+        MovePicker {
+            stage: PickStage::Priority,
+            priority,
+            moves,
+            index: 0,
+            sort,
+        }
+    }
+    fn next(&mut self, board: &mut Board) -> Option<Move> {
+        loop {
+            if let Some(m) = self
+                .moves
+                .get(self.index)
+            {
+                self.index += 1;
+                if self.stage != PickStage::Priority
+                    && self
+                        .priority
+                        .contains(m)
+                {
+                    continue;
+                }
+                return Some(m.clone());
+            }
+            // 这一阶段的着法取完了，生成下一阶段的着法
+            self.index = 0;
+            self.stage = match self.stage {
+                PickStage::Priority
+                    if board
+                        .config
+                        .staged_moves =>
+                {
+                    board.generate_move_into(true, &mut self.moves);
+                    PickStage::Captures
+                }
+                PickStage::Priority => {
+                    board.generate_move_into(false, &mut self.moves);
+                    PickStage::All
+                }
+                PickStage::Captures => {
+                    board.generate_move_into(false, &mut self.moves);
+                    self.moves
+                        .retain(|m| m.capture == Chess::None);
+                    PickStage::Quiets
+                }
+                PickStage::Quiets | PickStage::All | PickStage::Done => {
+                    self.moves.clear();
+                    self.stage = PickStage::Done;
+                    return None;
+                }
+            };
+            if self.sort {
+                board.sort_moves(&mut self.moves);
+            }
+        }
+    }
+    // 着法缓冲区还给棋盘，下一个节点接着用
+    fn finish(self, board: &mut Board) {
+        board
+            .move_buffers
+            .push(self.moves);
+    }
+}
+
 impl Board {
     pub fn init() -> Self {
         let mut board = Board {
@@ -451,16 +854,39 @@ impl Board {
                     Chess::Red(ChessType::Rook),
                 ],
             ],
+            piece_counts: [0; 7],
             turn: Player::Red,
             counter: 0,
+            nodes: 0,
             gen_counter: 0,
+            sort_counter: 0,
             move_history: vec![],
+            redo_stack: vec![],
             best_moves_last: vec![],
-            records: vec![],
+            records: Default::default(),
             zobrist_value: 0,
             zobrist_value_lock: 0,
             distance: 0,
+            halfmove_clock: 0,
+            fullmove_number: 1,
+            config: SearchConfig::default(),
+            eval_params: Arc::new(EvalParams::default()),
+            evaluator: default_evaluator(),
+            stop: Default::default(),
+            deadline: None,
+            time_up: false,
+            jitter_root: None,
+            root_distance: 0,
+            start_nodes: 0,
+            move_buffers: vec![],
+            repetitions: HashMap::new(),
+            history_table: vec![0; HISTORY_SIZE],
+            counter_move_table: vec![None; COUNTER_MOVE_SIZE],
+            checked: false,
+            check_history: vec![],
+            skip_null: false,
         };
+        board.piece_counts = board.count_pieces();
         board.zobrist_value = ZOBRIST_TABLE.calc_chesses(&board.chesses);
         board.zobrist_value_lock = ZOBRIST_TABLE_LOCK.calc_chesses(&board.chesses);
         board
@@ -468,17 +894,86 @@ impl Board {
     pub fn empty() -> Self {
         Board {
             chesses: [[Chess::None; BOARD_WIDTH as usize]; BOARD_HEIGHT as usize],
+            piece_counts: [0; 7],
             turn: Player::Red,
             counter: 0,
+            nodes: 0,
             gen_counter: 0,
+            sort_counter: 0,
             move_history: vec![],
+            redo_stack: vec![],
             best_moves_last: vec![],
-            records: vec![],
+            records: Default::default(),
             zobrist_value: 0,
             zobrist_value_lock: 0,
             distance: 0,
+            halfmove_clock: 0,
+            fullmove_number: 1,
+            config: SearchConfig::default(),
+            eval_params: Arc::new(EvalParams::default()),
+            evaluator: default_evaluator(),
+            stop: Default::default(),
+            deadline: None,
+            time_up: false,
+            jitter_root: None,
+            root_distance: 0,
+            start_nodes: 0,
+            move_buffers: vec![],
+            repetitions: HashMap::new(),
+            history_table: vec![0; HISTORY_SIZE],
+            counter_move_table: vec![None; COUNTER_MOVE_SIZE],
+            checked: false,
+            check_history: vec![],
+            skip_null: false,
+        }
+    }
+    // 只复制对局状态（棋子、走棋方、zobrist值、走过的着法和重复局面的记录、搜索和评价参数），
+    // 置换表、历史表、应着表这些搜索用的数据重新分配，停止标志也不共享
+    pub fn clone_position(&self) -> Board {
+        Board {
+            chesses: self.chesses,
+            piece_counts: self.piece_counts,
+            turn: self.turn,
+            move_history: self
+                .move_history
+                .clone(),
+            redo_stack: self
+                .redo_stack
+                .clone(),
+            zobrist_value: self.zobrist_value,
+            zobrist_value_lock: self.zobrist_value_lock,
+            distance: self.distance,
+            halfmove_clock: self.halfmove_clock,
+            fullmove_number: self.fullmove_number,
+            config: self.config.clone(),
+            eval_params: self
+                .eval_params
+                .clone(),
+            evaluator: self
+                .evaluator
+                .clone(),
+            repetitions: self
+                .repetitions
+                .clone(),
+            checked: self.checked,
+            check_history: self
+                .check_history
+                .clone(),
+            ..Board::empty()
         }
     }
+    // 换成实际对局的局面和走棋历史，保留置换表、历史表、应着表和搜索参数，
+    // 同一盘棋每次搜索前同步一下，可以复用之前搜索的结果
+    pub fn sync_position(&mut self, game: &Board) {
+        let old = std::mem::replace(self, game.clone_position());
+        self.records = old.records;
+        self.history_table = old.history_table;
+        self.counter_move_table = old.counter_move_table;
+        self.config = old.config;
+        self.eval_params = old.eval_params;
+        self.evaluator = old.evaluator;
+        self.stop = old.stop;
+    }
     pub fn from_fen(fen: &str) -> Self {
         let mut board = Board::empty();
         let mut parts = fen.split(" ");
@@ -510,7 +1005,191 @@ impl Board {
         if turn == "b" {
             board.turn = Player::Black;
         }
+        // 跳过两个不用的字段，没有计数时默认是"0 1"
+        let mut counters = parts.skip(2);
+        if let Some(halfmove) = counters
+            .next()
+            .and_then(|v| v.parse().ok())
+        {
+            board.halfmove_clock = halfmove;
+        }
+        if let Some(fullmove) = counters
+            .next()
+            .and_then(|v| v.parse().ok())
+        {
+            board.fullmove_number = fullmove;
+        }
+        board.checked = board.compute_checked();
+        board
+    }
+    // 校验FEN，用于界面输入这样不可信的来源，合法时和from_fen的结果一样
+    pub fn try_from_fen(fen: &str) -> Result<Self, FenParseError> {
+        // 字段之间可能有多个空格或者制表符，from_fen只认单个空格，先整理成单个空格分隔
+        let fields = fen
+            .split_whitespace()
+            .collect::<Vec<_>>();
+        let rows = fields
+            .first()
+            .copied()
+            .unwrap_or("")
+            .split("/")
+            .collect::<Vec<_>>();
+        if rows.len() != BOARD_HEIGHT as usize {
+            return Err(FenParseError::Rows(rows.len()));
+        }
+        for (i, row) in rows
+            .iter()
+            .enumerate()
+        {
+            let mut cols = 0;
+            for c in row.chars() {
+                if let Some(n) = c.to_digit(10) {
+                    cols += n as i32;
+                } else if FEN_MAP.contains_key(&c) {
+                    cols += 1;
+                } else {
+                    return Err(FenParseError::Chess(c));
+                }
+            }
+            if cols != BOARD_WIDTH {
+                return Err(FenParseError::Columns(i, cols));
+            }
+        }
+        let turn = fields
+            .get(1)
+            .copied()
+            .unwrap_or("");
+        if !["w", "r", "b"].contains(&turn) {
+            return Err(FenParseError::Turn(turn.to_owned()));
+        }
+        let board = Board::from_fen(&fields.join(" "));
+        for player in [Player::Red, Player::Black] {
+            let kings = board
+                .chesses
+                .iter()
+                .flatten()
+                .filter(|&&c| c.belong_to(player) && c.chess_type() == Some(ChessType::King))
+                .count();
+            // 将帅只能在九宫格内
+            if kings != 1
+                || board
+                    .king_position(player)
+                    .is_none()
+            {
+                return Err(FenParseError::King);
+            }
+        }
         board
+            .validate_position()
+            .map_err(FenParseError::Position)?;
+        Ok(board)
+    }
+    // 检查局面能否在对局中出现：双方各有一个将帅且在九宫格内，兵卒不会后退，
+    // 过河前也不会横走，不走棋的一方没有被将军（否则轮到走棋的一方直接吃将）
+    pub fn validate_position(&self) -> Result<(), PositionError> {
+        for player in [Player::Red, Player::Black] {
+            let kings = self
+                .pieces()
+                .filter(|(_, chess)| {
+                    chess.belong_to(player) && chess.chess_type() == Some(ChessType::King)
+                })
+                .map(|(pos, _)| pos)
+                .collect::<Vec<_>>();
+            if kings.len() != 1 {
+                return Err(PositionError::KingCount(player, kings.len()));
+            }
+            if !in_palace(kings[0], player) {
+                return Err(PositionError::KingOutsidePalace(player));
+            }
+        }
+        for (pos, chess) in self.pieces() {
+            if chess.chess_type() != Some(ChessType::Pawn) {
+                continue;
+            }
+            let player = chess
+                .player()
+                .unwrap();
+            let behind_start = match player {
+                Player::Red => pos.row > 6,
+                Player::Black => pos.row < 3,
+            };
+            if in_country(pos.row, player) && (behind_start || pos.col % 2 == 1) {
+                return Err(PositionError::PawnPosition(pos));
+            }
+        }
+        if self.is_checked(self.turn.next()) {
+            return Err(PositionError::OpponentInCheck);
+        }
+        Ok(())
+    }
+    pub fn to_fen(&self) -> String {
+        let mut rows = vec![];
+        for row in self.chesses.iter() {
+            let mut fen_row = String::new();
+            let mut empty = 0;
+            for chess in row {
+                if let Some((c, _)) = FEN_MAP
+                    .iter()
+                    .find(|(_, v)| *v == chess)
+                {
+                    if empty > 0 {
+                        fen_row.push_str(&empty.to_string());
+                        empty = 0;
+                    }
+                    fen_row.push(*c);
+                } else {
+                    empty += 1;
+                }
+            }
+            if empty > 0 {
+                fen_row.push_str(&empty.to_string());
+            }
+            rows.push(fen_row);
+        }
+        format!(
+            "{} {} - - {} {}",
+            rows.join("/"),
+            if self.turn == Player::Red { "w" } else { "b" },
+            self.no_capture_plies(),
+            self.fullmove()
+        )
+    }
+    // 距离上次吃子走了多少步，用于判断不吃子的和棋
+    pub fn no_capture_plies(&self) -> i32 {
+        let mut plies = 0;
+        for m in self
+            .move_history
+            .iter()
+            .rev()
+        {
+            if m.capture != Chess::None {
+                return plies;
+            }
+            plies += 1;
+        }
+        self.halfmove_clock + plies
+    }
+    // 当前的回合数，黑方走完一步后加一
+    pub fn fullmove(&self) -> i32 {
+        self.fullmove_number
+            + self
+                .move_history
+                .iter()
+                .filter(|m| m.player == Player::Black)
+                .count() as i32
+    }
+    #[cfg(feature = "serde")]
+    pub fn save_to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string(self)
+    }
+    #[cfg(feature = "serde")]
+    pub fn load_from_json(json: &str) -> serde_json::Result<Self> {
+        let mut board: Board = serde_json::from_str(json)?;
+        board.piece_counts = board.count_pieces();
+        board.zobrist_value = ZOBRIST_TABLE.calc_chesses(&board.chesses);
+        board.zobrist_value_lock = ZOBRIST_TABLE_LOCK.calc_chesses(&board.chesses);
+        board.checked = board.compute_checked();
+        Ok(board)
     }
     pub fn apply_move(&mut self, m: &Move) {
         let chess = self.chess_at(m.from);
@@ -519,8 +1198,16 @@ impl Board {
         self.zobrist_value = ZOBRIST_TABLE.apply_move(self.zobrist_value, m);
         self.zobrist_value_lock = ZOBRIST_TABLE_LOCK.apply_move(self.zobrist_value_lock, m);
         self.turn = m.player.next();
+        self.debug_check_zobrist();
+        self.check_history
+            .push(self.checked);
+        self.checked = self.compute_checked();
     }
     pub fn do_move(&mut self, m: &Move) {
+        *self
+            .repetitions
+            .entry(self.repetition_key())
+            .or_insert(0) += 1;
         self.apply_move(m);
         self.distance += 1;
         self.move_history
@@ -533,9 +1220,194 @@ impl Board {
         self.zobrist_value = ZOBRIST_TABLE.undo_move(self.zobrist_value, m);
         self.zobrist_value_lock = ZOBRIST_TABLE_LOCK.undo_move(self.zobrist_value_lock, m);
         self.turn = m.player;
+        self.debug_check_zobrist();
+        self.checked = match self
+            .check_history
+            .pop()
+        {
+            Some(checked) => checked,
+            None => self.compute_checked(),
+        };
         self.distance -= 1;
         self.move_history
             .pop();
+        let key = self.repetition_key();
+        if let Some(count) = self
+            .repetitions
+            .get_mut(&key)
+        {
+            *count -= 1;
+            if *count == 0 {
+                self.repetitions
+                    .remove(&key);
+            }
+        }
+    }
+    // 最近走的一步，界面用来标出上一步的起点和落点
+    pub fn last_move(&self) -> Option<Move> {
+        self.move_history
+            .last()
+            .cloned()
+    }
+    // 已经走了多少步（半回合）
+    pub fn ply(&self) -> usize {
+        self.move_history
+            .len()
+    }
+    // 只由棋子摆法决定的zobrist值和校验值，不含走棋方
+    pub fn zobrist_without_turn(&self) -> (u64, u64) {
+        (self.zobrist_value, self.zobrist_value_lock)
+    }
+    fn repetition_key(&self) -> (u64, u64, bool) {
+        (
+            self.zobrist_value,
+            self.zobrist_value_lock,
+            self.turn == Player::Red,
+        )
+    }
+    // 当前局面之前出现过，查表判断，不用遍历走棋历史
+    pub fn is_repetition(&self) -> bool {
+        self.repetitions
+            .contains_key(&self.repetition_key())
+    }
+    // 走完这步后的局面之前出现过，会形成重复局面
+    pub fn leads_to_repetition(&self, m: &Move) -> bool {
+        let key = (
+            ZOBRIST_TABLE.apply_move(self.zobrist_value, m),
+            ZOBRIST_TABLE_LOCK.apply_move(self.zobrist_value_lock, m),
+            m.player.next() == Player::Red,
+        );
+        self.repetitions
+            .contains_key(&key)
+    }
+    // 遍历走棋历史，返回当前局面之前出现过几次，吃子之前的局面不可能重复，遇到吃子就停止
+    pub fn rep_status(&self) -> u32 {
+        let (mut value, mut lock) = (self.zobrist_value, self.zobrist_value_lock);
+        let mut count = 0;
+        for m in self
+            .move_history
+            .iter()
+            .rev()
+        {
+            value = ZOBRIST_TABLE.undo_move(value, m);
+            lock = ZOBRIST_TABLE_LOCK.undo_move(lock, m);
+            if m.player == self.turn
+                && value == self.zobrist_value
+                && lock == self.zobrist_value_lock
+            {
+                count += 1;
+            }
+            if m.capture != Chess::None {
+                break;
+            }
+        }
+        count
+    }
+    // 实际对局里当前局面出现了三次（之前出现过两次），用于判定和棋
+    pub fn is_threefold_repetition(&self) -> bool {
+        self.rep_status() >= 2
+    }
+    // 棋盘旋转180度并交换双方棋子颜色，得到双方互换的等价局面，走棋历史不保留
+    pub fn flipped(&self) -> Board {
+        let mut board = Board::empty();
+        for i in 0..BOARD_HEIGHT {
+            for j in 0..BOARD_WIDTH {
+                let pos = Position::new(i, j);
+                board.set_chess(
+                    pos.flip(),
+                    self.chess_at(pos)
+                        .swap_color(),
+                );
+            }
+        }
+        board.turn = self.turn.next();
+        board.config = self.config.clone();
+        board.eval_params = self
+            .eval_params
+            .clone();
+        board.evaluator = self
+            .evaluator
+            .clone();
+        board.zobrist_value = ZOBRIST_TABLE.calc_chesses(&board.chesses);
+        board.zobrist_value_lock = ZOBRIST_TABLE_LOCK.calc_chesses(&board.chesses);
+        board.checked = board.compute_checked();
+        board
+    }
+    // 被吃掉的棋子，(红方被吃的子, 黑方被吃的子)，由走棋历史推导，悔棋后自动更新
+    pub fn captured_pieces(&self) -> (Vec<ChessType>, Vec<ChessType>) {
+        let mut red = vec![];
+        let mut black = vec![];
+        for m in self
+            .move_history
+            .iter()
+        {
+            match m.capture {
+                Chess::Red(ct) => red.push(ct),
+                Chess::Black(ct) => black.push(ct),
+                Chess::None => {}
+            }
+        }
+        (red, black)
+    }
+    // debug模式下校验增量更新的zobrist值与重新计算的一致
+    fn debug_check_zobrist(&self) {
+        debug_assert_eq!(
+            self.zobrist_value,
+            ZOBRIST_TABLE.calc_chesses(&self.chesses),
+            "zobrist值与棋盘不一致"
+        );
+        debug_assert_eq!(
+            self.zobrist_value_lock,
+            ZOBRIST_TABLE_LOCK.calc_chesses(&self.chesses),
+            "zobrist校验值与棋盘不一致"
+        );
+    }
+    // 对局中走一步棋，之前悔掉的着法不能再重做
+    pub fn play_move(&mut self, m: &Move) {
+        self.do_move(m);
+        self.redo_stack
+            .clear();
+    }
+    // 悔一步棋
+    pub fn undo_last(&mut self) -> Option<Move> {
+        let m = self
+            .move_history
+            .last()?
+            .clone();
+        self.undo_move(&m);
+        self.redo_stack
+            .push(m.clone());
+        Some(m)
+    }
+    // 一步步悔棋回到第target_ply步之后的局面，悔掉的棋可以重做，目标超过已走的步数时不变并返回false
+    pub fn goto_ply(&mut self, target_ply: usize) -> bool {
+        if target_ply > self.ply() {
+            return false;
+        }
+        while self.ply() > target_ply {
+            self.undo_last();
+        }
+        true
+    }
+    // 人机对弈时悔棋，回到人最近一次走棋之前的局面，不管最后一步是谁走的；
+    // 人执黑而电脑先走时不会悔掉电脑的第一步，人还没走过棋时不变并返回false
+    pub fn undo_to_human_turn(&mut self, human: Player) -> bool {
+        let Some(ply) = self
+            .move_history
+            .iter()
+            .rposition(|m| m.player == human)
+        else {
+            return false;
+        };
+        self.goto_ply(ply)
+    }
+    // 重做最近悔掉的一步棋
+    pub fn redo_last(&mut self) -> Option<Move> {
+        let m = self
+            .redo_stack
+            .pop()?;
+        self.do_move(&m);
+        Some(m)
     }
     pub fn chess_at(&self, pos: Position) -> Chess {
         if in_board(pos) {
@@ -545,7 +1417,24 @@ impl Board {
         }
     }
     pub fn set_chess(&mut self, pos: Position, chess: Chess) {
-        self.chesses[pos.row as usize][pos.col as usize] = chess;
+        let cell = &mut self.chesses[pos.row as usize][pos.col as usize];
+        if let Some(ct) = cell.chess_type() {
+            self.piece_counts[ct.value() as usize] -= 1;
+        }
+        if let Some(ct) = chess.chess_type() {
+            self.piece_counts[ct.value() as usize] += 1;
+        }
+        *cell = chess;
+    }
+    // 重新数一遍棋盘上每种棋子的数量
+    fn count_pieces(&self) -> [i32; 7] {
+        let mut counts = [0; 7];
+        for (_, chess) in self.pieces() {
+            if let Some(ct) = chess.chess_type() {
+                counts[ct.value() as usize] += 1;
+            }
+        }
+        counts
     }
     pub fn has_chess_between(&self, posa: Position, posb: Position) -> bool {
         if posa.row == posb.row {
@@ -618,159 +1507,242 @@ impl Board {
             false
         }
     }
+    // 当前走棋方是否被将军，读缓存的结果，直接用set_chess改了棋盘后不会更新
+    pub fn in_check(&self) -> bool {
+        self.checked
+    }
+    // 缺了将帅的局面(只在测试里出现)不算被将军
+    fn compute_checked(&self) -> bool {
+        self.king_position(Player::Red)
+            .is_some()
+            && self
+                .king_position(Player::Black)
+                .is_some()
+            && self.is_checked(self.turn)
+    }
     pub fn is_checked(&self, player: Player) -> bool {
         let position_base = self
             .king_position(player)
             .unwrap();
-
-        // 是否被炮将军
-        let targets = self.generate_move_for_chess_type(ChessType::Cannon, position_base);
-        for pos in targets {
-            if self
-                .chess_at(pos)
-                .belong_to(player.next())
-            {
-                if let Some(ChessType::Cannon) = self
-                    .chess_at(pos)
-                    .chess_type()
-                {
-                    return true;
-                }
-            }
+        self.is_attacked(position_base, player.next()) || self.king_eye_to_eye()
+    }
+    // pos是否被by一方的棋子攻击，不考虑走了之后自己是否被将军，也不包括将帅对脸
+    pub fn is_attacked(&self, pos: Position, by: Player) -> bool {
+        self.find_attackers(pos, by, |_| true)
+    }
+    // 正在将军player的对方棋子的位置，双将时有两个，将帅对脸时包括对方的将帅
+    pub fn checkers(&self, player: Player) -> Vec<Position> {
+        let mut checkers = vec![];
+        let Some(king) = self.king_position(player) else {
+            return checkers;
+        };
+        self.find_attackers(king, player.next(), |p| {
+            checkers.push(p);
+            false
+        });
+        if self
+            .king_position(player.next())
+            .is_some()
+            && self.king_eye_to_eye()
+        {
+            checkers.push(
+                self.king_position(player.next())
+                    .unwrap(),
+            );
         }
-        // 是否被车将军
-        let targets = self.generate_move_for_chess_type(ChessType::Rook, position_base);
-        for pos in targets {
-            if self
-                .chess_at(pos)
-                .belong_to(player.next())
-            {
-                if let Some(ChessType::Rook) = self
-                    .chess_at(pos)
-                    .chess_type()
-                {
-                    return true;
+        checkers
+    }
+    // player被牵制的棋子，走开后己方的将会被对方的车、炮或对脸的将攻击：
+    // 车（将）和己方将之间只隔这一个子；炮和己方将之间隔两个子，其中己方的子走开后剩下的正好当炮架
+    pub fn pinned_pieces(&self, player: Player) -> Vec<Position> {
+        let mut pinned = vec![];
+        let Some(king) = self.king_position(player) else {
+            return pinned;
+        };
+        for (dr, dc) in [(-1, 0), (1, 0), (0, -1), (0, 1)] {
+            // 沿这个方向从将开始遇到的前三个棋子
+            let mut line = Vec::with_capacity(3);
+            let mut pos = Position::new(king.row + dr, king.col + dc);
+            while in_board(pos) && line.len() < 3 {
+                if self.chess_at(pos) != Chess::None {
+                    line.push(pos);
                 }
+                pos = Position::new(pos.row + dr, pos.col + dc);
+            }
+            let own = |i: usize| {
+                line.get(i)
+                    .is_some_and(|&p| {
+                        self.chess_at(p)
+                            .belong_to(player)
+                    })
+            };
+            let enemy = |i: usize, types: &[ChessType]| {
+                line.get(i)
+                    .is_some_and(|&p| {
+                        let chess = self.chess_at(p);
+                        chess.belong_to(player.next())
+                            && types.contains(
+                                &chess
+                                    .chess_type()
+                                    .unwrap(),
+                            )
+                    })
+            };
+            if own(0) && enemy(1, &[ChessType::Rook, ChessType::King]) {
+                pinned.push(line[0]);
+            }
+            if enemy(2, &[ChessType::Cannon]) {
+                pinned.extend(
+                    line[..2]
+                        .iter()
+                        .filter(|&&p| {
+                            self.chess_at(p)
+                                .belong_to(player)
+                        }),
+                );
             }
         }
+        pinned
+    }
+    // 攻击pos的by一方棋子的位置和类型，按子力从小到大排列，静态交换评估可以从最便宜的子开始
+    pub fn attackers_of(&self, pos: Position, by: Player) -> Vec<(Position, ChessType)> {
+        let mut attackers = vec![];
+        self.find_attackers(pos, by, |p| {
+            attackers.push((
+                p,
+                self.chess_at(p)
+                    .chess_type()
+                    .unwrap(),
+            ));
+            false
+        });
+        attackers.sort_by_key(|(_, ct)| ct.see_value());
+        attackers
+    }
+    // 依次找出攻击pos的by一方棋子，对每个调用found，found返回true时停止查找并返回true
+    fn find_attackers<F>(&self, pos: Position, by: Player, mut found: F) -> bool
+    where
+        F: FnMut(Position) -> bool,
+    {
+        let attacker = |p: Position, ct: ChessType| {
+            let chess = self.chess_at(p);
+            chess.belong_to(by) && chess.chess_type() == Some(ct)
+        };
 
-        // 是否被马将军
-        let mut targets = vec![];
-        if self.chess_at(
-            position_base
-                .up(1)
-                .left(1),
-        ) == Chess::None
-        {
-            targets.push(
-                position_base
-                    .up(2)
-                    .left(1),
-            );
-            targets.push(
-                position_base
-                    .up(1)
-                    .left(2),
-            );
+        let mut targets = Vec::with_capacity(BOARD_HEIGHT as usize + BOARD_WIDTH as usize);
+        // 是否被炮攻击
+        self.generate_move_for_chess_type(ChessType::Cannon, pos, &mut targets);
+        for &p in targets.iter() {
+            if attacker(p, ChessType::Cannon) && found(p) {
+                return true;
+            }
         }
-        if self.chess_at(
-            position_base
-                .down(1)
-                .left(1),
-        ) == Chess::None
-        {
-            targets.push(
-                position_base
-                    .down(2)
-                    .left(1),
-            );
-            targets.push(
-                position_base
-                    .down(1)
-                    .left(2),
-            );
+        // 是否被车攻击
+        self.generate_move_for_chess_type(ChessType::Rook, pos, &mut targets);
+        for &p in targets.iter() {
+            if attacker(p, ChessType::Rook) && found(p) {
+                return true;
+            }
         }
-        if self.chess_at(
-            position_base
-                .up(1)
-                .right(1),
-        ) == Chess::None
-        {
-            targets.push(
-                position_base
-                    .up(2)
-                    .right(1),
-            );
-            targets.push(
-                position_base
-                    .up(1)
-                    .right(2),
-            );
+
+        // 是否被马攻击，马腿是马往pos方向走的第一格
+        for (dr, dc) in [
+            (-2i32, -1i32),
+            (-2, 1),
+            (2, -1),
+            (2, 1),
+            (-1, -2),
+            (1, -2),
+            (-1, 2),
+            (1, 2),
+        ] {
+            let Some(p) = pos.offset(dr, dc) else {
+                continue;
+            };
+            let leg = if dr.abs() == 2 {
+                pos.offset(dr / 2, dc)
+            } else {
+                pos.offset(dr, dc / 2)
+            };
+            if leg.is_some_and(|leg| self.chess_at(leg) == Chess::None)
+                && attacker(p, ChessType::Knight)
+                && found(p)
+            {
+                return true;
+            }
         }
-        if self.chess_at(
-            position_base
-                .down(1)
-                .right(1),
-        ) == Chess::None
-        {
-            targets.push(
-                position_base
-                    .down(2)
-                    .right(1),
-            );
-            targets.push(
-                position_base
-                    .down(1)
-                    .right(2),
-            );
+
+        // 是否被兵攻击，兵过了河才能横着走
+        let forward = if by == Player::Red {
+            pos.offset(1, 0)
+        } else {
+            pos.offset(-1, 0)
+        };
+        if let Some(p) = forward {
+            if attacker(p, ChessType::Pawn) && found(p) {
+                return true;
+            }
         }
-        for pos in targets {
-            if self
-                .chess_at(pos)
-                .belong_to(player.next())
+        if !in_country(pos.row, by) {
+            for p in [pos.offset(0, -1), pos.offset(0, 1)]
+                .into_iter()
+                .flatten()
             {
-                if let Some(ChessType::Knight) = self
-                    .chess_at(pos)
-                    .chess_type()
-                {
+                if attacker(p, ChessType::Pawn) && found(p) {
                     return true;
                 }
             }
         }
 
-        // 是否被兵将军
-        for pos in vec![
-            position_base.left(1),
-            position_base.right(1),
-            if player == Player::Red {
-                position_base.up(1)
-            } else {
-                position_base.down(1)
-            },
-        ] {
-            if self
-                .chess_at(pos)
-                .belong_to(player.next())
-            {
-                if let Some(ChessType::Pawn) = self
-                    .chess_at(pos)
-                    .chess_type()
+        // 是否被将、士攻击，只能在九宫格内
+        if in_palace(pos, by) {
+            for p in [pos.up(1), pos.down(1), pos.left(1), pos.right(1)] {
+                if attacker(p, ChessType::King) && found(p) {
+                    return true;
+                }
+            }
+            for (dr, dc) in [(-1, -1), (-1, 1), (1, -1), (1, 1)] {
+                let p = Position::new(pos.row + dr, pos.col + dc);
+                if attacker(p, ChessType::Advisor) && found(p) {
+                    return true;
+                }
+            }
+        }
+        // 是否被象攻击，象不能过河，象眼不能有棋子
+        if in_country(pos.row, by) {
+            for (dr, dc) in [(-1, -1), (-1, 1), (1, -1), (1, 1)] {
+                let p = Position::new(pos.row + 2 * dr, pos.col + 2 * dc);
+                if self.chess_at(Position::new(pos.row + dr, pos.col + dc)) == Chess::None
+                    && attacker(p, ChessType::Bishop)
+                    && found(p)
                 {
                     return true;
                 }
             }
         }
-        return self.king_eye_to_eye();
+        false
     }
+    // 棋子所有可能的落点，写入调用方提供的缓冲区，落点是否在棋盘内、是否有己方棋子由调用方判断
     pub fn generate_move_for_chess_type(
         &self,
         ct: ChessType,
         position_base: Position,
-    ) -> Vec<Position> {
-        let mut targets = vec![];
+        targets: &mut Vec<Position>,
+    ) {
+        self.generate_targets_for(ct, position_base, self.turn, targets);
+    }
+    // 和generate_move_for_chess_type一样，但是按player一方的棋子计算，不一定是当前走棋方
+    fn generate_targets_for(
+        &self,
+        ct: ChessType,
+        position_base: Position,
+        player: Player,
+        targets: &mut Vec<Position>,
+    ) {
+        targets.clear();
         match ct {
             ChessType::King => {
-                targets.append(&mut vec![
+                targets.extend([
                     position_base.up(1),
                     position_base.down(1),
                     position_base.left(1),
@@ -778,7 +1750,7 @@ impl Board {
                 ]);
             }
             ChessType::Advisor => {
-                targets.append(&mut vec![
+                targets.extend([
                     position_base
                         .up(1)
                         .left(1),
@@ -844,7 +1816,7 @@ impl Board {
                 }
             }
             ChessType::Knight => {
-                if self.turn == Player::Red {
+                if player == Player::Red {
                     if self.chess_at(position_base.up(1)) == Chess::None {
                         targets.push(
                             position_base
@@ -922,7 +1894,7 @@ impl Board {
                 }
             }
             ChessType::Rook => {
-                if self.turn == Player::Red {
+                if player == Player::Red {
                     for delta in 1..(position_base.row + 1) {
                         targets.push(position_base.up(delta));
                         if self.chess_at(position_base.up(delta)) != Chess::None {
@@ -1019,22 +1991,49 @@ impl Board {
             }
             ChessType::Pawn => {
                 // 过河兵可以左右走
-                if !in_country(position_base.row, self.turn) {
+                if !in_country(position_base.row, player) {
                     targets.push(position_base.left(1));
                     targets.push(position_base.right(1));
                 }
-                if self.turn == Player::Black {
-                    targets.push(position_base.down(1))
+                // 走到底线的兵不能再往前，只能左右走
+                let forward = if player == Player::Black {
+                    position_base.offset(1, 0)
                 } else {
-                    targets.push(position_base.up(1));
-                }
+                    position_base.offset(-1, 0)
+                };
+                targets.extend(forward);
             }
         }
-        targets
+    }
+    // 落点是否在该棋子能走的区域内，并且没有己方的棋子
+    fn is_valid_target(&self, ct: ChessType, target: Position) -> bool {
+        self.is_valid_target_for(ct, target, self.turn)
+    }
+    fn is_valid_target_for(&self, ct: ChessType, target: Position, player: Player) -> bool {
+        let valid = if ct == ChessType::King || ct == ChessType::Advisor {
+            // 帅和士要在九宫格内
+            in_palace(target, player)
+        } else if ct == ChessType::Bishop {
+            // 象不能过河
+            in_country(target.row, player) && in_board(target)
+        } else {
+            in_board(target)
+        };
+        valid
+            && !self
+                .chess_at(target)
+                .belong_to(player)
     }
     pub fn generate_move(&mut self, capture_only: bool) -> Vec<Move> {
-        self.gen_counter += 1;
         let mut moves = vec![];
+        self.generate_move_into(capture_only, &mut moves);
+        moves
+    }
+    // 和generate_move一样，但是复用调用方的缓冲区，搜索中频繁调用时减少内存分配
+    pub fn generate_move_into(&mut self, capture_only: bool, moves: &mut Vec<Move>) {
+        self.gen_counter += 1;
+        moves.clear();
+        let mut targets = Vec::with_capacity(BOARD_HEIGHT as usize + BOARD_WIDTH as usize);
         for i in 0..BOARD_HEIGHT {
             for j in 0..BOARD_WIDTH {
                 let position_base = Position::new(i, j);
@@ -1042,7 +2041,7 @@ impl Board {
                 let chess = self.chess_at(position_base);
                 if chess.belong_to(self.turn) {
                     if let Some(ct) = chess.chess_type() {
-                        let targets = self.generate_move_for_chess_type(ct, position_base);
+                        self.generate_move_for_chess_type(ct, position_base, &mut targets);
                         let move_base = Move {
                             player: self.turn,
                             from: position_base,
@@ -1050,30 +2049,15 @@ impl Board {
                             chess,
                             capture: Chess::None,
                         };
-                        for target in targets {
-                            let valid = if ct == ChessType::King || ct == ChessType::Advisor {
-                                // 帅和士要在九宫格内
-                                in_palace(target, self.turn)
-                            } else if ct == ChessType::Bishop {
-                                // 象不能过河
-                                in_country(target.row, self.turn) && in_board(target)
-                            } else {
-                                in_board(target)
-                            };
-
-                            if valid {
-                                if !self
-                                    .chess_at(target)
-                                    .belong_to(self.turn)
-                                    && (!capture_only
-                                        || self
-                                            .chess_at(target)
-                                            .chess_type()
-                                            .is_some())
-                                {
-                                    moves
-                                        .push(move_base.with_target(target, self.chess_at(target)));
-                                }
+                        for &target in targets.iter() {
+                            if self.is_valid_target(ct, target)
+                                && (!capture_only
+                                    || self
+                                        .chess_at(target)
+                                        .chess_type()
+                                        .is_some())
+                            {
+                                moves.push(move_base.with_target(target, self.chess_at(target)));
                             }
                         }
                     }
@@ -1096,86 +2080,751 @@ impl Board {
                         .value()),
             )
         });
+    }
+    // 走完这步棋后对方是否被将军
+    pub fn gives_check(&mut self, m: &Move) -> bool {
+        self.do_move(m);
+        let checked = self.in_check();
+        self.undo_move(m);
+        checked
+    }
+    // 走的这个子到了新位置后是否直接将军对方，不用真的走棋，也不算闪将，着法排序用
+    fn checks_directly(&self, m: &Move) -> bool {
+        let Some(king) = self.king_position(m.player.next()) else {
+            return false;
+        };
+        let to = m.to;
+        // 走完后新位置和对方将之间的棋子数，原来的位置已经空出来
+        let between = || {
+            let (dr, dc) = ((king.row - to.row).signum(), (king.col - to.col).signum());
+            let mut p = Position::new(to.row + dr, to.col + dc);
+            let mut count = 0;
+            while p != king {
+                if p != m.from && self.chess_at(p) != Chess::None {
+                    count += 1;
+                }
+                p = Position::new(p.row + dr, p.col + dc);
+            }
+            count
+        };
+        let in_line = to.row == king.row || to.col == king.col;
+        match m.chess.chess_type() {
+            Some(ChessType::Rook) => in_line && between() == 0,
+            Some(ChessType::Cannon) => in_line && between() == 1,
+            Some(ChessType::Knight) => {
+                let (dr, dc) = (king.row - to.row, king.col - to.col);
+                if dr.abs() * dc.abs() != 2 {
+                    return false;
+                }
+                // 马腿是马往将的方向走的第一格
+                let leg = if dr.abs() == 2 {
+                    Position::new(to.row + dr / 2, to.col)
+                } else {
+                    Position::new(to.row, to.col + dc / 2)
+                };
+                self.chess_at(leg) == Chess::None
+            }
+            // 兵向前走一格或者过河后横走一格就到将的位置
+            Some(ChessType::Pawn) => {
+                let forward = if m.player == Player::Red { -1 } else { 1 };
+                (king.col == to.col && king.row - to.row == forward)
+                    || (king.row == to.row && (king.col - to.col).abs() == 1)
+            }
+            _ => false,
+        }
+    }
+    // 平静的局面：走棋方没有被将军，也没有合法的吃子着法
+    pub fn is_quiet(&mut self) -> bool {
+        if self.in_check() {
+            return false;
+        }
+        let captures = self.generate_move(true);
+        !captures
+            .iter()
+            .any(|m| self.is_king_safe_after(m))
+    }
+    // 着法是吃子、将军、吃子并将军还是普通着法
+    pub fn classify_move(&mut self, m: &Move) -> MoveKind {
+        MoveKind::new(m.capture != Chess::None, self.gives_check(m))
+    }
+    // 所有能将军的合法着法
+    pub fn checking_moves(&mut self) -> Vec<Move> {
+        let mut moves = self.generate_move(false);
+        moves.retain(|m| self.is_king_safe_after(m) && self.gives_check(m));
         moves
     }
-    // 简单的评价，双方每个棋子的子力之和的差
-    pub fn evaluate(&self, player: Player) -> i32 {
-        let mut red_score = 0;
-        let mut black_score = 0;
-        for i in 0..BOARD_HEIGHT as usize {
-            for j in 0..BOARD_WIDTH as usize {
-                let chess = self.chess_at(Position::new(i as i32, j as i32));
-                if let Some(ct) = chess.chess_type() {
-                    let pos = if chess.belong_to(Player::Black) {
-                        Position::new(i as i32, j as i32).flip()
-                    } else {
-                        Position::new(i as i32, j as i32)
-                    };
-                    let score = match ct {
-                        ChessType::King => KING_VALUE_TABLE[pos.row as usize][pos.col as usize],
-                        ChessType::Advisor => {
-                            ADVISOR_VALUE_TABLE[pos.row as usize][pos.col as usize]
+    // 吃子按MVV/LVA排序，将军的着法和应着额外加分，分数相同时按历史表排序；
+    // 每个着法都要判断是否将军，只看走的子直接将军，不真的走棋
+    pub fn sort_moves(&mut self, moves: &mut [Move]) {
+        self.sort_counter += moves.len() as u64;
+        let counter_move = self.counter_move();
+        moves.sort_by_cached_key(|m| {
+            let mut value = m.capture.value() - m.chess.value();
+            let kind = MoveKind::new(m.capture != Chess::None, self.checks_directly(m));
+            if kind.is_check() {
+                value += CHECK_BONUS;
+            } else if kind == MoveKind::Quiet && counter_move.as_ref() == Some(m) {
+                value += COUNTER_MOVE_BONUS;
+            }
+            (-value, -self.history_score(m))
+        });
+    }
+    // 对方上一步着法的应着，刚走过空着时上一步是自己走的，没有应着
+    pub fn counter_move(&self) -> Option<Move> {
+        if !self
+            .config
+            .counter_moves
+        {
+            return None;
+        }
+        let last = self
+            .move_history
+            .last()
+            .filter(|m| m.player != self.turn)?;
+        self.counter_move_table
+            .get(Board::counter_move_index(last))?
+            .clone()
+            .filter(|m| m.player == self.turn)
+    }
+    fn counter_move_index(last: &Move) -> usize {
+        last.from.to_index() * BOARD_SQUARES + last.to.to_index()
+    }
+    fn update_counter_move(&mut self, m: &Move) {
+        let Some(last) = self
+            .move_history
+            .last()
+            .filter(|l| l.player != m.player)
+        else {
+            return;
+        };
+        let i = Board::counter_move_index(last);
+        // 从json加载的棋盘没有应着表
+        if self
+            .counter_move_table
+            .len()
+            < COUNTER_MOVE_SIZE
+        {
+            self.counter_move_table
+                .resize(COUNTER_MOVE_SIZE, None);
+        }
+        self.counter_move_table[i] = Some(m.clone());
+    }
+    fn history_index(m: &Move) -> Option<usize> {
+        let ct = m
+            .chess
+            .chess_type()?;
+        Some((m.player.value() * 7 + ct.value()) as usize * BOARD_SQUARES + m.to.to_index())
+    }
+    pub fn history_score(&self, m: &Move) -> i32 {
+        Board::history_index(m)
+            .and_then(|i| {
+                self.history_table
+                    .get(i)
+                    .copied()
+            })
+            .unwrap_or(0)
+    }
+    // 发生截断的着法按深度的平方加分，不超过HISTORY_MAX
+    fn update_history(&mut self, m: &Move, depth: i32) {
+        let Some(i) = Board::history_index(m) else {
+            return;
+        };
+        // 从json加载的棋盘没有历史表
+        if self
+            .history_table
+            .len()
+            < HISTORY_SIZE
+        {
+            self.history_table
+                .resize(HISTORY_SIZE, 0);
+        }
+        self.history_table[i] = (self.history_table[i] + depth * depth).min(HISTORY_MAX);
+    }
+    // 每次搜索完把历史表减半，之前局面的分数逐渐失效
+    pub fn age_history(&mut self) {
+        for v in self
+            .history_table
+            .iter_mut()
+        {
+            *v /= 2;
+        }
+    }
+    // 某个棋子所有合法的落子位置，不是当前行棋方的棋子返回空
+    pub fn legal_targets(&mut self, from: Position) -> Vec<Position> {
+        if !self
+            .chess_at(from)
+            .belong_to(self.turn)
+        {
+            return vec![];
+        }
+        let mut targets = vec![];
+        for m in self.generate_move(false) {
+            if m.from == from && self.is_king_safe_after(&m) {
+                targets.push(m.to);
+            }
+        }
+        targets
+    }
+    // 走棋方所有某一种棋子的合法着法
+    pub fn moves_of_type(&mut self, ct: ChessType) -> Vec<Move> {
+        let mut moves = vec![];
+        let mut targets = vec![];
+        for i in 0..BOARD_HEIGHT {
+            for j in 0..BOARD_WIDTH {
+                let position_base = Position::new(i, j);
+                let chess = self.chess_at(position_base);
+                if !chess.belong_to(self.turn) || chess.chess_type() != Some(ct) {
+                    continue;
+                }
+                self.generate_move_for_chess_type(ct, position_base, &mut targets);
+                let move_base = Move {
+                    player: self.turn,
+                    from: position_base,
+                    to: position_base,
+                    chess,
+                    capture: Chess::None,
+                };
+                for &target in targets.iter() {
+                    if self.is_valid_target(ct, target) {
+                        let m = move_base.with_target(target, self.chess_at(target));
+                        if self.is_king_safe_after(&m) {
+                            moves.push(m);
                         }
-                        ChessType::Bishop => BISHOP_VALUE_TABLE[pos.row as usize][pos.col as usize],
-                        ChessType::Knight => KNIGHT_VALUE_TABLE[pos.row as usize][pos.col as usize],
-                        ChessType::Rook => ROOK_VALUE_TABLE[pos.row as usize][pos.col as usize],
-                        ChessType::Cannon => CANNON_VALUE_TABLE[pos.row as usize][pos.col as usize],
-                        ChessType::Pawn => PAWN_VALUE_TABLE[pos.row as usize][pos.col as usize],
-                    };
-                    if chess.belong_to(Player::Black) {
-                        black_score += score
-                    } else {
-                        red_score += score
                     }
                 }
             }
         }
-        if player == Player::Red {
-            red_score - black_score + INITIATIVE_BONUS
-        } else {
-            black_score - red_score + INITIATIVE_BONUS
+        moves
+    }
+    // 走完这步后己方没有被将军，着法是否符合棋子的走法由调用方保证
+    fn is_king_safe_after(&mut self, m: &Move) -> bool {
+        self.do_move(m);
+        let safe = !self.is_checked(self.turn.next());
+        self.undo_move(m);
+        safe
+    }
+    // 着法是否合法，走一步再撤回来判断，不复制棋盘，返回后棋盘不变
+    pub fn is_move_legal(&mut self, m: &Move) -> bool {
+        self.is_valid_move_for(m, self.turn) && self.is_king_safe_after(m)
+    }
+    // side一方的棋子按规则能这样走，不要求轮到side走棋，也不检查走完后自己是否被将军；
+    // 着法属于另一方时返回false
+    pub fn is_valid_move_for(&self, m: &Move, side: Player) -> bool {
+        if m.player != side
+            || self.chess_at(m.from) != m.chess
+            || self.chess_at(m.to) != m.capture
+            || !m
+                .chess
+                .belong_to(side)
+        {
+            return false;
         }
+        let Some(ct) = m.chess.chess_type() else {
+            return false;
+        };
+        let mut targets = vec![];
+        self.generate_targets_for(ct, m.from, side, &mut targets);
+        targets.contains(&m.to) && self.is_valid_target_for(ct, m.to, side)
     }
-    pub fn find_record(&self) -> Option<Record> {
-        if let Some(record) =
-            &self.records[(self.zobrist_value & (RECORD_SIZE - 1) as u64) as usize]
+    // 依次走一串着法，要么全走，要么一步不走：遇到不合法的着法时撤回已经走的着法，返回它的序号
+    pub fn apply_moves(&mut self, moves: &[Move]) -> Result<(), usize> {
+        for (i, m) in moves
+            .iter()
+            .enumerate()
         {
-            if record.zobrist_lock == self.zobrist_value_lock && self.turn == record.turn {
-                Some(record.clone())
-            } else {
-                None
+            if !self.is_move_legal(m) {
+                for m in moves[..i]
+                    .iter()
+                    .rev()
+                {
+                    self.undo_move(m);
+                }
+                return Err(i);
             }
-        } else {
-            None
+            self.do_move(m);
+        }
+        Ok(())
+    }
+    // 只有共享引用时使用，会复制一份棋盘
+    pub fn is_move_legal_cloned(&self, m: &Move) -> bool {
+        self.clone()
+            .is_move_legal(m)
+    }
+    // 双方都没有车马炮兵，谁也不可能将死对方
+    pub fn is_insufficient_material(&self) -> bool {
+        self.chesses
+            .iter()
+            .flatten()
+            .all(|chess| {
+                matches!(
+                    chess.chess_type(),
+                    None | Some(ChessType::King | ChessType::Advisor | ChessType::Bishop)
+                )
+            })
+    }
+    // 当前的对局状态，轮到走棋的一方无棋可走就输了
+    pub fn game_status(&mut self) -> GameStatus {
+        let has_legal_move = self
+            .generate_move(false)
+            .iter()
+            .any(|m| self.is_king_safe_after(m));
+        if !has_legal_move {
+            return match self.turn {
+                Player::Red => GameStatus::BlackWin,
+                Player::Black => GameStatus::RedWin,
+            };
+        }
+        if self.is_insufficient_material() {
+            return GameStatus::Draw;
+        }
+        // 同一局面出现三次，按长将、长捉判胜负，都没有就是和棋
+        if self.is_threefold_repetition() {
+            if let Some(status) = self.repetition_verdict() {
+                return status;
+            }
+        }
+        GameStatus::Playing
+    }
+    // 当前局面的对局结果记号，导出棋谱时用
+    pub fn result_token(&mut self) -> &'static str {
+        self.game_status()
+            .result_token()
+    }
+    // 刚走完的着法m捉了对方哪些子：走的这个子能吃到，并且对方没有保护的子，不算将和兵，
+    // 有保护的子可以长捉，将帅和兵卒捉子也不算
+    fn chased_pieces(&mut self, m: &Move) -> Vec<Position> {
+        let ct = m
+            .chess
+            .chess_type()
+            .unwrap();
+        if matches!(ct, ChessType::King | ChessType::Pawn) {
+            return vec![];
         }
+        let turn = self.turn;
+        self.turn = m.player;
+        let mut targets = vec![];
+        self.generate_move_for_chess_type(ct, m.to, &mut targets);
+        let chased = targets
+            .into_iter()
+            .filter(|&target| {
+                let chess = self.chess_at(target);
+                chess.belong_to(turn)
+                    && !matches!(
+                        chess.chess_type(),
+                        Some(ChessType::King) | Some(ChessType::Pawn)
+                    )
+                    && self.is_valid_target(ct, target)
+                    && !self.is_attacked(target, turn)
+            })
+            .collect();
+        self.turn = turn;
+        chased
     }
-    pub fn add_record(&mut self, record: Record) {
-        if let Some(old_record) =
-            &self.records[(self.zobrist_value & (RECORD_SIZE - 1) as u64) as usize]
+    // 当前局面重复出现时判定结果：循环里一方每步都将军或者一直捉同一个没有保护的子，
+    // 另一方没有，那么这一方判负，双方都是或都不是则判和，局面没有重复返回None
+    pub fn repetition_verdict(&mut self) -> Option<GameStatus> {
+        // 找到上一次出现当前局面的位置，这之后的着法构成一个循环
+        let (mut value, mut lock) = (self.zobrist_value, self.zobrist_value_lock);
+        let mut start = None;
+        for (i, m) in self
+            .move_history
+            .iter()
+            .enumerate()
+            .rev()
         {
-            // 如果已存在，用深度较大的覆盖，depth越小，深度越大
-            if record.depth < old_record.depth {
-                self.records[(self.zobrist_value & (RECORD_SIZE - 1) as u64) as usize] =
-                    Some(record);
+            value = ZOBRIST_TABLE.undo_move(value, m);
+            lock = ZOBRIST_TABLE_LOCK.undo_move(lock, m);
+            if m.player == self.turn
+                && value == self.zobrist_value
+                && lock == self.zobrist_value_lock
+            {
+                start = Some(i);
+                break;
             }
-        } else {
-            self.records[(self.zobrist_value & (RECORD_SIZE - 1) as u64) as usize] = Some(record);
+            if m.capture != Chess::None {
+                break;
+            }
+        }
+        let cycle = self.move_history[start?..].to_vec();
+        for m in cycle.iter().rev() {
+            self.undo_move(m);
+        }
+        // 下标0是红方，1是黑方
+        let mut checks = [true, true];
+        let mut chased: [Option<Vec<Position>>; 2] = [None, None];
+        for m in cycle.iter() {
+            // 被捉的子被吃掉就不算了，走动了就跟着更新位置
+            for list in chased
+                .iter_mut()
+                .flatten()
+            {
+                list.retain(|&p| p != m.to);
+                for p in list.iter_mut() {
+                    if *p == m.from {
+                        *p = m.to;
+                    }
+                }
+            }
+            self.do_move(m);
+            let side = if m.player == Player::Red { 0 } else { 1 };
+            checks[side] &= self.in_check();
+            let now = self.chased_pieces(m);
+            chased[side] = Some(match chased[side].take() {
+                Some(before) => before
+                    .into_iter()
+                    .filter(|p| now.contains(p))
+                    .collect(),
+                None => now,
+            });
+        }
+        let forbidden = |side: usize| {
+            checks[side]
+                || chased[side]
+                    .as_ref()
+                    .is_some_and(|list| !list.is_empty())
+        };
+        Some(match (forbidden(0), forbidden(1)) {
+            (true, false) => GameStatus::BlackWin,
+            (false, true) => GameStatus::RedWin,
+            _ => GameStatus::Draw,
+        })
+    }
+    // 静态交换评估，双方轮流用最便宜的棋子在目标格上互相吃，返回这一串交换后的净得子力，
+    // 每一方都可以选择不再继续吃，不考虑牵制
+    pub fn see(&self, m: &Move) -> i32 {
+        let mut board = Board::empty();
+        board.chesses = self.chesses;
+        board.piece_counts = self.piece_counts;
+        board.set_chess(m.from, Chess::None);
+        board.set_chess(m.to, m.chess);
+        board.turn = m.player.next();
+        // gains[i]表示第i次吃子后，吃子一方的净得子力（假设对方会继续吃回来）
+        let mut gains = vec![m
+            .capture
+            .see_value()];
+        let mut attacker = m.chess;
+        let mut moves = vec![];
+        loop {
+            board.generate_move_into(true, &mut moves);
+            let next = moves
+                .iter()
+                .filter(|n| n.to == m.to)
+                .min_by_key(|n| n.chess.see_value());
+            if let Some(n) = next {
+                gains.push(
+                    attacker.see_value()
+                        - gains
+                            .last()
+                            .unwrap(),
+                );
+                attacker = n.chess;
+                board.set_chess(n.from, Chess::None);
+                board.set_chess(n.to, n.chess);
+                board.turn = board.turn.next();
+            } else {
+                break;
+            }
+        }
+        // 从最后一次吃子往回推，吃了会亏的话就不吃
+        while gains.len() > 1 {
+            let gain = gains.pop().unwrap();
+            let last = gains
+                .last_mut()
+                .unwrap();
+            *last = -(-*last).max(gain);
+        }
+        gains[0]
+    }
+    // 棋盘上所有的棋子，(位置, 棋子)，跳过空位
+    pub fn pieces(&self) -> impl Iterator<Item = (Position, Chess)> + '_ {
+        (0..BOARD_HEIGHT)
+            .flat_map(|i| (0..BOARD_WIDTH).map(move |j| Position::new(i, j)))
+            .map(|pos| (pos, self.chess_at(pos)))
+            .filter(|(_, chess)| *chess != Chess::None)
+    }
+    // 一方棋盘上的棋子数，包括帅
+    pub fn piece_count(&self, player: Player) -> usize {
+        self.pieces()
+            .filter(|(_, chess)| chess.belong_to(player))
+            .count()
+    }
+    // 一方棋子的子力之和
+    pub fn material(&self, player: Player) -> i32 {
+        self.pieces()
+            .filter(|(_, chess)| chess.belong_to(player))
+            .filter_map(|(_, chess)| chess.chess_type())
+            .map(|ct| {
+                self.eval_params
+                    .material_value(ct)
+            })
+            .sum()
+    }
+    // 子力差，红方减黑方
+    pub fn material_balance(&self) -> i32 {
+        self.material(Player::Red) - self.material(Player::Black)
+    }
+    // 对局阶段，由车马炮的数量算出，不用遍历棋盘；评价参数换了以后也不用重新计算，
+    // TOTAL_PHASE表示开局，0表示只剩帅仕相兵的残局
+    pub fn phase(&self) -> i32 {
+        let material: i32 = [ChessType::Rook, ChessType::Knight, ChessType::Cannon]
+            .into_iter()
+            .map(|ct| {
+                self.piece_counts[ct.value() as usize]
+                    * self
+                        .eval_params
+                        .phase_weight(ct)
+            })
+            .sum();
+        (material * TOTAL_PHASE / OPENING_PHASE_MATERIAL).min(TOTAL_PHASE)
+    }
+    // player视角的局面分，由棋盘上设置的评价函数计算
+    pub fn evaluate(&self, player: Player) -> i32 {
+        self.evaluator
+            .evaluate(self, player)
+    }
+    // 内置评价的各项分数，用来调试和调整评价参数；换了评价函数时各项之和不等于evaluate
+    pub fn eval_breakdown(&self, player: Player) -> EvalBreakdown {
+        eval_breakdown(self, player)
+    }
+    // 杀棋分数与离根节点的步数有关，置换表里保存相对当前局面的分数，取出时再换算回来
+    pub fn find_record(&self) -> Option<Record> {
+        let mut record =
+            self.records
+                .find(self.zobrist_value, self.zobrist_value_lock, self.turn)?;
+        if record.value < KILL {
+            record.value += self.search_ply();
+        } else if record.value > -KILL {
+            record.value -= self.search_ply();
+        }
+        Some(record)
+    }
+    pub fn add_record(&mut self, mut record: Record) {
+        if record.value < KILL {
+            record.value -= self.search_ply();
+        } else if record.value > -KILL {
+            record.value += self.search_ply();
+        }
+        self.records
+            .add(self.zobrist_value, record);
+    }
+    // 离本次搜索根节点的步数
+    fn search_ply(&self) -> i32 {
+        self.distance - self.root_distance
+    }
+    // 取一个空闲的着法缓冲区，用完后放回move_buffers，递归时相当于每层一个缓冲区
+    fn take_move_buffer(&mut self) -> Vec<Move> {
+        self.move_buffers
+            .pop()
+            .unwrap_or_default()
+    }
+    pub fn is_stopped(&self) -> bool {
+        self.time_up
+            || self
+                .stop
+                .load(Ordering::Relaxed)
+            || self
+                .config
+                .node_limit
+                .is_some_and(|limit| self.nodes - self.start_nodes >= limit)
+    }
+    // 开始一次新的搜索，节点数上限从现在算起
+    fn start_search(&mut self) {
+        self.start_nodes = self.nodes;
+    }
+    // 到了截止时间后一直处于停止状态，直到这次搜索结束
+    fn check_time(&mut self) {
+        if self.nodes & (TIME_CHECK_NODES - 1) == 0
+            && self
+                .deadline
+                .is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            self.time_up = true;
         }
     }
-    pub fn alpha_beta_pvs(&mut self, depth: i32, mut alpha: i32, beta: i32) -> (i32, Option<Move>) {
-        // if let Some(record) = self.find_record() {
-        //     if record.depth <= depth {
-        //         return (record.value, record.best_move);
-        //     }
-        // }
+    pub fn alpha_beta_pvs(&mut self, depth: i32, alpha: i32, beta: i32) -> (i32, Option<Move>) {
+        self.start_search();
+        self.search_from(self.distance, depth, alpha, beta)
+    }
+    // 迭代加深等多次搜索组成的一次搜索里的一层，全窗口搜索，节点数上限接着之前的算
+    fn search_depth(&mut self, depth: i32) -> (i32, Option<Move>) {
+        self.search_from(self.distance, depth, MIN, MAX)
+    }
+    // 根节点在root_distance处的搜索，先走了一步再搜索时根节点是走之前的局面
+    fn search_from(
+        &mut self,
+        root_distance: i32,
+        depth: i32,
+        alpha: i32,
+        beta: i32,
+    ) -> (i32, Option<Move>) {
+        self.root_distance = root_distance;
+        // 一条线路上延伸的总深度不超过根节点的搜索深度
+        self.alpha_beta_pvs_internal(depth, alpha, beta, depth)
+    }
+    fn alpha_beta_pvs_internal(
+        &mut self,
+        mut depth: i32,
+        mut alpha: i32,
+        mut beta: i32,
+        mut extension_budget: i32,
+    ) -> (i32, Option<Move>) {
+        let null_allowed = !std::mem::take(&mut self.skip_null);
+        if self.is_stopped() {
+            return (0, None);
+        }
+        // 走回了之前出现过的局面，按和棋处理；根节点是实际对局里的局面，要给出着法，不算重复
+        if self.distance > self.root_distance && self.is_repetition() {
+            return (self.draw_value(), None);
+        }
+        // 一条线路走得太长还没有结果，判为和棋，避免无休止地递归下去
+        if self.search_ply()
+            >= self
+                .config
+                .max_distance
+        {
+            return (self.draw_value(), None);
+        }
+        // 杀棋步数剪枝，这里的分数不会低于当前被将死，也不会高于当前将死对方，
+        // 如果已经找到了更快的杀棋，这个分支不用再搜索
+        if self
+            .config
+            .mate_distance_pruning
+        {
+            alpha = alpha.max(MIN + self.search_ply());
+            beta = beta.min(MAX - self.search_ply());
+            if alpha >= beta {
+                return (alpha, None);
+            }
+        }
+        // 被将军时延伸搜索，延伸用掉的深度从预算里扣除，避免长将时搜索爆炸
+        let check_extension = self
+            .config
+            .check_extension;
+        if check_extension > 0 && extension_budget >= check_extension && self.in_check() {
+            depth += check_extension;
+            extension_budget -= check_extension;
+        }
+        let mut hash_move = None;
+        let mut singular = false;
+        if let Some(record) = self.find_record() {
+            if record.depth >= depth {
+                match record.flag {
+                    RecordFlag::Exact => return (record.value, record.best_move),
+                    RecordFlag::Lower if record.value >= beta => return (record.value, None),
+                    RecordFlag::Upper if record.value <= alpha => return (record.value, None),
+                    _ => {}
+                }
+            }
+            // 置换表着法的分数是下界或准确值，且不是杀棋分数时，用降低深度的搜索验证其他着法
+            if let Some(hm) = &record.best_move {
+                if self
+                    .config
+                    .singular_extension
+                    && depth >= SINGULAR_MIN_DEPTH
+                    && extension_budget >= 1
+                    && record.depth >= depth - 3
+                    && record.flag != RecordFlag::Upper
+                    && record.value > KILL
+                    && record.value < -KILL
+                {
+                    singular = self.is_singular(
+                        hm,
+                        record.value - SINGULAR_MARGIN,
+                        depth / 2,
+                        extension_budget,
+                    );
+                }
+            }
+            hash_move = record.best_move;
+        }
         if depth == 0 {
             self.counter += 1;
-            return (self.quies(alpha, beta), None);
+            return (self.quies_internal(alpha, beta, 0), None);
+        }
+        // 剃刀剪枝，零窗口的浅层节点局面分远低于alpha时先做静态搜索，
+        // 静态搜索也到不了alpha就不再展开这一层；
+        // 对方没有车马炮时常常要靠不吃子的将军杀棋，静态搜索看不到，不做剃刀剪枝
+        if self.config.razoring
+            && depth <= RAZOR_DEPTH
+            && beta - alpha == 1
+            && alpha > KILL
+            && alpha < -KILL
+            && !self.in_check()
+            && self.has_attackers(self.turn.next())
+            && self.evaluate(self.turn) + RAZOR_MARGIN * depth <= alpha
+        {
+            // 没有吃子可走的平静局面，静态搜索也只能站着不动，结果就是alpha
+            if !self
+                .config
+                .quies_checks
+                && self.is_quiet()
+            {
+                return (alpha, None);
+            }
+            self.counter += 1;
+            let v = self.quies_internal(alpha, beta, 0);
+            if v <= alpha {
+                return (v, None);
+            }
+        }
+        // 空着裁剪，让对方连走两步仍然能达到beta，说明这个局面足够好，
+        // 没有车马炮时容易出现等着，不做空着
+        if self
+            .config
+            .null_move
+            && null_allowed
+            && depth >= 2
+            && beta - alpha == 1
+            && beta > KILL
+            && beta < -KILL
+            && !self.in_check()
+            && self.has_attackers(self.turn)
+        {
+            let reduction = NULL_MOVE_REDUCTION + depth / 6;
+            self.do_null_move();
+            self.skip_null = true;
+            let (v, _) = self.alpha_beta_pvs_internal(
+                (depth - 1 - reduction).max(0),
+                -beta,
+                -beta + 1,
+                extension_budget,
+            );
+            self.undo_null_move();
+            if self.is_stopped() {
+                return (0, None);
+            }
+            if -v >= beta {
+                // 深度较大时用减少后的深度正常搜索一次，也能达到beta才裁剪
+                let verified = if self
+                    .config
+                    .null_move_verify
+                    && depth >= NULL_MOVE_VERIFY_DEPTH
+                {
+                    self.skip_null = true;
+                    let (v, _) = self.alpha_beta_pvs_internal(
+                        depth - reduction,
+                        alpha,
+                        beta,
+                        extension_budget,
+                    );
+                    v >= beta
+                } else {
+                    true
+                };
+                if verified {
+                    return (beta, None);
+                }
+            }
         }
+        self.nodes += 1;
+        self.check_time();
         let mut count = 0; // 记录尝试了多少种着法
 
-        // 优先尝试迭代深度搜索的上一层搜索结果
-        let mut moves = self.generate_move(false);
+        // 置换表里的最佳着法优先搜索
+        let mut priority = Vec::with_capacity(2);
+        if let Some(hm) = &hash_move {
+            priority.push(hm.clone());
+        }
         // 如果符合上次搜索的着法线路，那么优先按此线路搜索下去
         for (i, m) in self
             .best_moves_last
@@ -1190,42 +2839,108 @@ impl Board {
                     break;
                 }
             } else {
-                moves.insert(0, m.clone());
+                if !priority.contains(m) {
+                    priority.push(m.clone());
+                }
                 break;
             }
         }
+        // 离叶子较远的节点，排序更准带来的剪枝收益大于判断将军的开销
+        let mut picker = MovePicker::new(self, priority, depth >= 2);
+        let alpha_origin = alpha;
+        // 前沿节点，局面分加上该棋子的边际仍不能超过alpha时，不吃子也不将军的着法不用再搜索
+        let futility_value = if self
+            .config
+            .futility_pruning
+            && depth == 1
+            && !self.in_check()
+        {
+            Some(self.evaluate(self.turn))
+        } else {
+            None
+        };
         let mut best_move = None;
-        for m in moves {
+        while let Some(m) = picker.next(self) {
+            // 唯一的好着法多搜一层
+            let extension = if singular && hash_move.as_ref() == Some(&m) {
+                1
+            } else {
+                0
+            };
+            let (child_depth, child_budget) = (depth - 1 + extension, extension_budget - extension);
             self.do_move(&m);
             if self.is_checked(self.turn.next()) {
                 self.undo_move(&m);
                 continue;
             }
             count = count + 1;
+            // 已经走完这步棋，直接用是否吃子和对方是否被将军来分类，只剪普通着法
+            if let Some(v) = futility_value {
+                if MoveKind::new(m.capture != Chess::None, self.in_check()) == MoveKind::Quiet
+                    && v + m
+                        .chess
+                        .chess_type()
+                        .unwrap()
+                        .futility_margin()
+                        <= alpha
+                {
+                    self.undo_move(&m);
+                    continue;
+                }
+            }
+            // 吃掉最后的进攻子力后双方都无法取胜，直接算和棋
+            let draw = m.capture != Chess::None && self.is_insufficient_material();
+            // 根节点的着法加上随机分数，搜索窗口相应地移动，比较时用加过的分数；
+            // 杀棋分数不加，免得改变杀棋的步数，窗口和结果按同样的规则换算
+            let jitter = self.root_jitter(&m);
+            let shift = |v: i32, delta: i32| {
+                if v > KILL && v < -KILL {
+                    v + delta
+                } else {
+                    v
+                }
+            };
+            let (low, high) = (shift(alpha, -jitter), shift(beta, -jitter));
             // 先使用0宽窗口进行搜索
-            let (v, bmt) = self.alpha_beta_pvs(depth - 1, -(alpha + 1), -alpha);
+            let (v, bmt) = if draw {
+                (self.draw_value(), None)
+            } else {
+                self.alpha_beta_pvs_internal(child_depth, -(low + 1), -low, child_budget)
+            };
 
             let mut best_value = -v;
             let mut bm = bmt;
-            if best_value == MIN || (best_value > alpha && best_value < beta) {
-                let (v, bmt) = self.alpha_beta_pvs(depth - 1, -beta, -alpha);
-                // self.add_record(Record {
-                //     value: -v,
-                //     depth,
-                //     best_move: bmt.clone(),
-                //     zobrist_lock: self.zobrist_value_lock,
-                //     turn: self.turn,
-                // });
+            if !draw && (best_value == MIN || (best_value > low && best_value < high)) {
+                let (v, bmt) = self.alpha_beta_pvs_internal(child_depth, -high, -low, child_budget);
                 best_value = -v;
                 bm = bmt;
             }
+            best_value = shift(best_value, jitter);
 
             // let (v, bmt) = self.alpha_beta(depth - 1, -beta, -alpha);
             // let mut best_value = -v;
             // let mut bm = bmt;
 
+            if self.is_stopped() {
+                self.undo_move(&m);
+                picker.finish(self);
+                return (0, None);
+            }
             if best_value >= beta {
                 self.undo_move(&m);
+                picker.finish(self);
+                if m.capture == Chess::None {
+                    self.update_history(&m, depth);
+                    self.update_counter_move(&m);
+                }
+                self.add_record(Record {
+                    value: best_value,
+                    depth,
+                    flag: RecordFlag::Lower,
+                    best_move: Some(m.clone()),
+                    zobrist_lock: self.zobrist_value_lock,
+                    turn: self.turn,
+                });
                 return (best_value, None);
             }
             if best_value > alpha {
@@ -1235,64 +2950,510 @@ impl Board {
 
             self.undo_move(&m);
         }
+        picker.finish(self);
 
         // 如果尝试的着法数为0,说明已经被绝杀
-        // 深度减分，深度越小，说明越早被将死，局面分应该越低，由于depth是递减的，
-        // 所以深度越小，depth越大，减去depth的局面分就越低
-        return (if count == 0 { KILL - depth } else { alpha }, best_move);
-    }
-    pub fn quies(&mut self, mut alpha: i32, beta: i32) -> i32 {
-        if self.distance > MAX_DEPTH {
-            return self.evaluate(self.turn);
+        // 按离根节点的步数减分，越早被将死，局面分越低
+        let value = if count == 0 {
+            MIN + self.search_ply()
+        } else {
+            alpha
+        };
+        self.add_record(Record {
+            value,
+            depth,
+            flag: if count == 0 || alpha > alpha_origin {
+                RecordFlag::Exact
+            } else {
+                RecordFlag::Upper
+            },
+            best_move: best_move.clone(),
+            zobrist_lock: self.zobrist_value_lock,
+            turn: self.turn,
+        });
+        return (value, best_move);
+    }
+    // 空着，只交换走棋方，走棋历史、将军历史和重复局面表都不记录，和实际走过的着法保持一一对应；
+    // 被将军时不能走空着，否则轮到对方走棋时这一方还被将军，局面不合法
+    fn do_null_move(&mut self) {
+        debug_assert!(!self.in_check(), "被将军时不能走空着");
+        self.turn = self.turn.next();
+        self.distance += 1;
+        self.checked = self.compute_checked();
+    }
+    // 走空着前没有被将军，撤回时不用查将军历史
+    fn undo_null_move(&mut self) {
+        self.turn = self.turn.next();
+        self.distance -= 1;
+        self.checked = false;
+    }
+    // 一方还有车马炮
+    fn has_attackers(&self, player: Player) -> bool {
+        self.pieces()
+            .any(|(_, chess)| {
+                chess.belong_to(player)
+                    && matches!(
+                        chess.chess_type(),
+                        Some(ChessType::Rook | ChessType::Knight | ChessType::Cannon)
+                    )
+            })
+    }
+    // 根节点的着法按种子和起点、落点得到的随机分数，其他节点是0
+    fn root_jitter(&self, m: &Move) -> i32 {
+        let jitter = self
+            .config
+            .root_jitter;
+        if jitter <= 0 || self.jitter_root != Some(self.distance - 1) {
+            return 0;
         }
-        let v = self.evaluate(self.turn);
-        if v >= beta {
-            return beta;
+        let mut state = self
+            .config
+            .jitter_seed
+            ^ (m.from.to_index() * BOARD_SQUARES + m.to.to_index()) as u64;
+        (next_seeded(&mut state) % (2 * jitter as u64 + 1)) as i32 - jitter
+    }
+    // 和棋对当前走棋方的分数，根节点走棋方按contempt减分，对方相应加分
+    fn draw_value(&self) -> i32 {
+        let contempt = self.config.contempt;
+        if self.search_ply() % 2 == 0 {
+            -contempt
+        } else {
+            contempt
         }
-        if v > alpha {
-            alpha = v
+    }
+    // 除了hash_move以外的着法，用depth深度、singular_beta为界的0宽窗口搜索，都达不到时返回true
+    fn is_singular(
+        &mut self,
+        hash_move: &Move,
+        singular_beta: i32,
+        depth: i32,
+        extension_budget: i32,
+    ) -> bool {
+        let mut moves = self.take_move_buffer();
+        self.generate_move_into(false, &mut moves);
+        let mut singular = true;
+        for m in moves.iter() {
+            if m == hash_move {
+                continue;
+            }
+            self.do_move(m);
+            if self.is_checked(self.turn.next()) {
+                self.undo_move(m);
+                continue;
+            }
+            let (v, _) = self.alpha_beta_pvs_internal(
+                depth - 1,
+                -singular_beta,
+                -singular_beta + 1,
+                extension_budget,
+            );
+            self.undo_move(m);
+            if -v >= singular_beta || self.is_stopped() {
+                singular = false;
+                break;
+            }
         }
-        let moves = if self.is_checked(self.turn.next()) {
-            self.generate_move(false)
+        self.move_buffers
+            .push(moves);
+        singular
+    }
+    // 被吃的子在局面分里最多值多少，位置分表里含有子力价值，和evaluate是同一个尺度
+    fn capture_value(&self, m: &Move) -> i32 {
+        let Some(ct) = m
+            .capture
+            .chess_type()
+        else {
+            return 0;
+        };
+        let pos = if m
+            .capture
+            .belong_to(Player::Black)
+        {
+            m.to.flip()
         } else {
-            self.generate_move(true)
+            m.to
         };
-        for m in moves {
+        let (opening, end) = self
+            .eval_params
+            .position_value(ct, pos.row as usize, pos.col as usize);
+        opening.max(end)
+    }
+    pub fn quies(&mut self, alpha: i32, beta: i32) -> i32 {
+        self.start_search();
+        self.root_distance = self.distance;
+        self.quies_internal(alpha, beta, 0)
+    }
+    // ply是进入静态搜索后走的步数，超过上限或节点数用完时直接返回局面分
+    fn quies_internal(&mut self, mut alpha: i32, beta: i32, ply: i32) -> i32 {
+        self.nodes += 1;
+        self.check_time();
+        if self.search_ply() > MAX_DEPTH
+            || ply
+                >= self
+                    .config
+                    .quies_max_ply
+            || self.is_stopped()
+        {
+            return self.evaluate(self.turn);
+        }
+        // 被将军时不能不走棋，不做站着不动的截断，所有应将的着法都要搜索
+        let in_check = self.in_check();
+        let mut stand_pat = MIN;
+        if !in_check {
+            let v = self.evaluate(self.turn);
+            if v >= beta {
+                return beta;
+            }
+            if v > alpha {
+                alpha = v
+            }
+            stand_pat = v;
+        }
+        let mut moves = self.take_move_buffer();
+        let capture_only = !in_check;
+        // 马和炮常常不吃子就能将军，逼对方应将，第一层静态搜索也要看这些着法，之后的层数不再搜索
+        let quiet_checks = capture_only
+            && ply == 0
+            && self
+                .config
+                .quies_checks;
+        self.generate_move_into(capture_only && !quiet_checks, &mut moves);
+        let mut legal = 0;
+        for i in 0..moves.len() {
+            let m = moves[i].clone();
+            let quiet = m.capture == Chess::None;
+            if capture_only && quiet && !self.gives_check(&m) {
+                continue;
+            }
+            // 没被将军时，交换下来会亏子的吃子不用搜索，吃掉不比自己便宜的子一定不会亏
+            if capture_only
+                && !quiet
+                && self
+                    .config
+                    .quies_see
+                && m.capture
+                    .see_value()
+                    < m.chess.see_value()
+                && self.see(&m) < 0
+            {
+                continue;
+            }
+            // 吃掉这个子之后局面分仍然差得太远，不可能超过alpha
+            if capture_only
+                && !quiet
+                && self
+                    .config
+                    .delta_pruning
+                && stand_pat + self.capture_value(&m) + DELTA_MARGIN < alpha
+            {
+                continue;
+            }
             self.do_move(&m);
             if self.is_checked(self.turn.next()) {
                 self.undo_move(&m);
                 continue;
             }
-            let v = -self.quies(-beta, -alpha);
+            legal += 1;
+            let v = -self.quies_internal(-beta, -alpha, ply + 1);
             self.undo_move(&m);
+            if self.is_stopped() {
+                break;
+            }
             if v >= beta {
+                self.move_buffers
+                    .push(moves);
                 return beta;
             }
             if v > alpha {
                 alpha = v;
             }
         }
+        self.move_buffers
+            .push(moves);
+        // 被将军又没有应将的着法，已经被将死
+        if in_check && legal == 0 && !self.is_stopped() {
+            return MIN + self.search_ply();
+        }
         return alpha;
     }
-    pub fn iterative_deepening(&mut self, max_depth: i32) -> (i32, Option<Move>) {
-        if max_depth > 3 {
-            for depth in 3..max_depth + 1 {
-                // self.records = vec![RECORD_NONE; RECORD_SIZE as usize];
-                let (v, bm) = self.alpha_beta_pvs(depth, MIN, MAX);
-                if depth == max_depth {
-                    println!("第{}层: {:?}", depth, bm);
-                    return (v, bm);
+    pub fn iterative_deepening(&mut self, max_depth: i32) -> SearchResult {
+        let start = Instant::now();
+        let (nodes, gen_counter) = (self.nodes, self.gen_counter);
+        self.start_search();
+        let mut best = (0, None);
+        let mut depth_reached = 0;
+        // 第一层搜完之前不限时，保证总能返回一个着法
+        let deadline = self.deadline.take();
+        // 深度较大时从第3层开始逐层加深
+        let start_depth = if max_depth > 3 { 3 } else { max_depth };
+        self.jitter_root = Some(self.distance);
+        for depth in start_depth..max_depth + 1 {
+            let (v, bm) = self.search_depth(depth);
+            // 被中止的这一层结果不完整，返回上一层的结果
+            if self.is_stopped() {
+                break;
+            }
+            println!("第{}层: {:?}", depth, bm);
+            best = (v, bm);
+            depth_reached = depth;
+            self.deadline = deadline;
+            // 已经找到杀棋或者被杀，再加深也改变不了结果
+            if mate_distance(v).is_some() {
+                break;
+            }
+        }
+        self.deadline = None;
+        self.time_up = false;
+        self.jitter_root = None;
+        self.age_history();
+        let (score, best_move) = best;
+        let pv = match &best_move {
+            Some(m) => self.principal_variation(m),
+            None => vec![],
+        };
+        SearchResult {
+            score,
+            best_move,
+            pv,
+            nodes: self.nodes - nodes,
+            gen_nodes: (self.gen_counter - gen_counter) as u64,
+            depth_reached,
+            elapsed: start.elapsed(),
+        }
+    }
+    // 从最佳着法开始，沿着置换表里记录的最佳着法得到主要变例
+    pub fn principal_variation(&mut self, best_move: &Move) -> Vec<Move> {
+        let mut pv = vec![];
+        let mut m = Some(best_move.clone());
+        while let Some(next) = m {
+            // 置换表可能被覆盖或发生哈希冲突，走不通的着法就停下来
+            if pv.len() >= MAX_DEPTH as usize || !self.is_move_legal(&next) {
+                break;
+            }
+            self.do_move(&next);
+            pv.push(next);
+            m = self
+                .find_record()
+                .and_then(|r| r.best_move);
+        }
+        for m in pv.iter().rev() {
+            self.undo_move(m);
+        }
+        pv
+    }
+    // 在max_ply步以内找当前走棋方的杀棋，找到时返回走到将死为止的着法，双方的着法都包括在内
+    pub fn find_mate(&mut self, max_ply: i32) -> Option<Vec<Move>> {
+        self.start_search();
+        for depth in 1..max_ply + 1 {
+            let (value, best_move) = self.search_depth(depth);
+            if self.is_stopped() {
+                return None;
+            }
+            // 由杀棋分数得到将死需要的步数，被将死或者不是杀棋时是None，
+            // 将军延伸可能找到比搜索深度更远的杀棋，超过max_ply的不算
+            let Some(plies) = mate_distance(value).filter(|&p| p > 0 && p <= max_ply) else {
+                continue;
+            };
+            let mut line = vec![best_move?];
+            self.do_move(&line[0]);
+            // 不依赖置换表，之后每一步用剩下的步数重新搜索，输的一方会选最顽强的应着
+            for remaining in (1..plies).rev() {
+                let (_, m) = self.search_depth(remaining);
+                let Some(m) = m else {
+                    break;
+                };
+                self.do_move(&m);
+                line.push(m);
+            }
+            for m in line.iter().rev() {
+                self.undo_move(m);
+            }
+            return if line.len() == plies as usize {
+                Some(line)
+            } else {
+                None
+            };
+        }
+        None
+    }
+    // 分析模式，不限深度地迭代加深，每完成一层调用callback(深度, 分数, 主要变例)，直到设置了停止标志
+    pub fn analyze<F>(&mut self, mut callback: F) -> (i32, Option<Move>)
+    where
+        F: FnMut(i32, i32, &[Move]),
+    {
+        self.start_search();
+        let mut best = (0, None);
+        for depth in 1..MAX_DEPTH + 1 {
+            let (v, bm) = self.search_depth(depth);
+            if self.is_stopped() {
+                break;
+            }
+            let pv = match &bm {
+                Some(m) => self.principal_variation(m),
+                None => vec![],
+            };
+            callback(depth, v, &pv);
+            best = (v, bm);
+            // 没有着法可走，再加深也是一样的结果
+            if pv.is_empty() {
+                break;
+            }
+        }
+        best
+    }
+    // 对根节点的每个合法着法分别搜索，返回按分数从高到低排列的(分数, 着法)
+    pub fn root_move_scores(&mut self, depth: i32) -> Vec<(i32, Move)> {
+        self.start_search();
+        let mut scores = vec![];
+        for m in self.generate_move(false) {
+            if !self.is_king_safe_after(&m) {
+                continue;
+            }
+            scores.push((self.score_move(&m, depth), m));
+        }
+        scores.sort_by_key(|(v, _)| -v);
+        scores
+    }
+    // 走m之后搜索depth-1层，返回当前走棋方视角的分数，和alpha_beta_pvs(depth)的分数可以直接比较；
+    // m必须是合法着法，界面的教练模式用来检查刚走的一步比最好的着法差多少
+    pub fn evaluate_move(&mut self, m: &Move, depth: i32) -> i32 {
+        self.start_search();
+        self.score_move(m, depth)
+    }
+    fn score_move(&mut self, m: &Move, depth: i32) -> i32 {
+        let root_distance = self.distance;
+        self.do_move(m);
+        let (v, _) = self.search_from(root_distance, (depth - 1).max(0), MIN, MAX);
+        self.undo_move(m);
+        -v
+    }
+    // 给当前走棋方提示着法，搜索到指定深度，只返回着法不走棋
+    pub fn suggest_move(&mut self, depth: i32) -> Option<Move> {
+        self.start_search();
+        let mut best_move = None;
+        for depth in 1..depth.max(1) + 1 {
+            let (_, bm) = self.search_depth(depth);
+            if self.is_stopped() {
+                break;
+            }
+            if let Some(m) = bm {
+                if self.is_move_legal(&m) {
+                    best_move = Some(m);
                 }
-                self.best_moves_last = vec![];
-                self.best_moves_last
-                    .reverse();
-                println!("第{}层: {:?}", depth, self.best_moves_last);
             }
-        } else {
-            // self.records = vec![RECORD_NONE; RECORD_SIZE as usize];
-            return self.alpha_beta_pvs(max_depth, MIN, MAX);
         }
-        (0, None)
+        best_move
+    }
+    // 在分数和最佳着法相差不超过margin的前top_n个着法中随机选一个
+    pub fn pick_near_best(&mut self, depth: i32, top_n: usize, margin: i32) -> Option<Move> {
+        let scores = self.root_move_scores(depth);
+        let best = scores.first()?.0;
+        let candidates = scores
+            .into_iter()
+            .take(top_n)
+            .filter(|(v, _)| best - v <= margin)
+            .collect::<Vec<_>>();
+        let mut buf = [0; 4];
+        getrandom(&mut buf).unwrap();
+        let index = u32::from_be_bytes(buf) as usize % candidates.len();
+        Some(
+            candidates[index]
+                .1
+                .clone(),
+        )
+    }
+    // 按难度搜索最佳着法
+    pub fn search_by_difficulty(&mut self, difficulty: Difficulty) -> Option<Move> {
+        if let Some((top_n, margin)) = difficulty.random_pick() {
+            // 低难度在分数接近的前几个着法中随机选一个，故意走得弱一些
+            return self.pick_near_best(difficulty.max_depth(), top_n, margin);
+        }
+        let deadline = Instant::now() + difficulty.movetime();
+        self.start_search();
+        let mut best_move = None;
+        for depth in 1..difficulty.max_depth() + 1 {
+            let (_, bm) = self.search_depth(depth);
+            // 超时中止的这一层结果不完整，用上一层的着法
+            if self.is_stopped() {
+                break;
+            }
+            if bm.is_some() {
+                best_move = bm;
+            }
+            if Instant::now() >= deadline {
+                break;
+            }
+            // 第一层搜完之前不限时，保证总能返回一个着法，之后到时间就中止正在搜的这一层
+            self.deadline = Some(deadline);
+        }
+        self.deadline = None;
+        self.time_up = false;
+        best_move
+    }
+    // 多线程搜索(Lazy SMP)，各线程共享同一张置换表，各自做迭代加深，到时间后返回最深一层的结果
+    pub fn parallel_search(
+        &self,
+        threads: usize,
+        max_depth: i32,
+        deadline: Instant,
+    ) -> (i32, Option<Move>) {
+        let records = if self
+            .records
+            .is_empty()
+        {
+            Arc::new(TranspositionTable::new(RECORD_SIZE))
+        } else {
+            self.records.clone()
+        };
+        let stop = Arc::new(AtomicBool::new(false));
+        // (完成的深度, 分数, 最佳着法)
+        let best = Mutex::new((0, 0, None));
+        // 是否已经有线程搜完了一层
+        let completed = AtomicBool::new(false);
+        thread::scope(|s| {
+            let handles = (0..threads.max(1))
+                .map(|i| {
+                    let mut board = self.clone();
+                    board.records = records.clone();
+                    board.stop = stop.clone();
+                    board.start_search();
+                    let best = &best;
+                    let completed = &completed;
+                    s.spawn(move || {
+                        // 一半线程从第2层开始，让各线程搜索的节点错开
+                        for depth in 1 + (i % 2) as i32..max_depth + 1 {
+                            let (v, bm) = board.search_depth(depth);
+                            if board.is_stopped() {
+                                break;
+                            }
+                            let mut best = best.lock().unwrap();
+                            if bm.is_some() && depth > best.0 {
+                                *best = (depth, v, bm);
+                            }
+                            completed.store(true, Ordering::Relaxed);
+                        }
+                    })
+                })
+                .collect::<Vec<_>>();
+            while !handles
+                .iter()
+                .all(|h| h.is_finished())
+            {
+                // 到时间或者棋盘自己的停止标志被设置（比如UCCI的stop命令）都停止各线程，
+                // 但是要等到有线程搜完一层，保证总能返回一个着法
+                if (Instant::now() >= deadline || self.is_stopped())
+                    && completed.load(Ordering::Relaxed)
+                {
+                    stop.store(true, Ordering::Relaxed);
+                    break;
+                }
+                thread::sleep(Duration::from_millis(1));
+            }
+        });
+        let (_, v, bm) = best
+            .into_inner()
+            .unwrap();
+        (v, bm)
     }
 }
 
@@ -1300,6 +3461,32 @@ impl Board {
 mod tests {
     use crate::board::*;
 
+    // 按configure改好搜索参数后迭代加深搜索depth层，比较搜索开关打开和关闭时的结果用，
+    // 返回搜索结果和排序过的着法数
+    fn search_with(
+        fen: &str,
+        depth: i32,
+        configure: impl Fn(&mut SearchConfig),
+    ) -> (SearchResult, u64) {
+        let mut board = Board::from_fen(fen);
+        board.records = Arc::new(TranspositionTable::new(RECORD_SIZE));
+        configure(&mut board.config);
+        let result = board.iterative_deepening(depth);
+        (result, board.sort_counter)
+    }
+    // 按configure改好搜索参数后做静态搜索，返回分数和节点数
+    fn quies_with(
+        fen: &str,
+        alpha: i32,
+        beta: i32,
+        configure: impl Fn(&mut SearchConfig),
+    ) -> (i32, u64) {
+        let mut board = Board::from_fen(fen);
+        configure(&mut board.config);
+        let v = board.quies(alpha, beta);
+        (v, board.nodes)
+    }
+
     #[test]
     fn test_generate_move() {
         let mut board = Board::init();
@@ -1314,6 +3501,54 @@ mod tests {
         );
     }
     #[test]
+    fn test_last_rank_pawn() {
+        // 红兵走到黑方底线，只生成左右两步，不会生成棋盘外的落点
+        let board = Board::from_fen("3k5/9/9/9/9/9/9/9/9/4K4 w - - 0 1");
+        let mut targets = vec![];
+        board.generate_move_for_chess_type(ChessType::Pawn, "e9".into(), &mut targets);
+        assert_eq!(targets, vec!["d9".into(), "f9".into()]);
+        let mut board = Board::from_fen("3k5/4P4/9/9/9/9/9/9/9/4K4 w - - 0 1");
+        board
+            .play_moves(&["e8e9", "d9d8"])
+            .unwrap();
+        let mut targets = board
+            .generate_move(false)
+            .into_iter()
+            .filter(|m| m.chess == Chess::Red(ChessType::Pawn))
+            .map(|m| m.to)
+            .collect::<Vec<_>>();
+        targets.sort_by_key(|p| p.col);
+        assert_eq!(targets, vec!["d9".into(), "f9".into()]);
+        // 黑卒走到红方底线也一样
+        let board = Board::from_fen("3k5/9/9/9/9/9/9/9/9/4K4 b - - 0 1");
+        board.generate_move_for_chess_type(ChessType::Pawn, "c0".into(), &mut targets);
+        assert_eq!(targets, vec!["b0".into(), "d0".into()]);
+    }
+    #[test]
+    fn test_generate_move_into() {
+        let mut board = Board::from_fen(
+            "r1ba1a3/4kn3/2n1b4/pNp1p1p1p/4c4/6P2/P1P2R2P/1CcC5/9/2BAKAB2 w - - 0 1",
+        );
+        let mut buffer = vec![Move::stay(); 100];
+        for _ in 0..20 {
+            for capture_only in [true, false] {
+                board.generate_move_into(capture_only, &mut buffer);
+                assert_eq!(buffer, board.generate_move(capture_only));
+            }
+            let m = buffer
+                .iter()
+                .find(|m| {
+                    board.do_move(m);
+                    let checked = board.is_checked(board.turn.next());
+                    board.undo_move(m);
+                    !checked
+                })
+                .unwrap()
+                .clone();
+            board.do_move(&m);
+        }
+    }
+    #[test]
     fn test_is_checked() {
         let mut board = Board::init();
         for _i in 0..10_000 {
@@ -1331,10 +3566,10 @@ mod tests {
         let mut board = Board::init();
         for _i in 0..8_000 {
             let m = Move {
-                player: Player::Red,
+                player: Player::Black,
                 from: Position::new(0, 0),
                 to: Position::new(1, 0),
-                chess: Chess::Red(ChessType::Rook),
+                chess: Chess::Black(ChessType::Rook),
                 capture: Chess::None,
             };
             board.apply_move(&m);
@@ -1349,33 +3584,2126 @@ mod tests {
     }
 
     #[test]
-    fn test_evaluate() {
+    fn test_zobrist_random_sequence() {
         let mut board = Board::init();
-        board.apply_move(&Move {
-            player: Player::Red,
-            from: Position { row: 9, col: 8 },
-            to: Position { row: 7, col: 8 },
-            chess: Chess::Red(ChessType::Rook),
-            capture: Chess::None,
+        let zobrist_value = board.zobrist_value;
+        let zobrist_value_lock = board.zobrist_value_lock;
+        let mut seed: u64 = 0x2545F4914F6CDD1D;
+        let mut played = vec![];
+        for _i in 0..200 {
+            let moves = board.generate_move(false);
+            if moves.is_empty() {
+                break;
+            }
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            let m = moves[(seed >> 33) as usize % moves.len()].clone();
+            board.do_move(&m);
+            played.push(m);
+        }
+        while let Some(m) = played.pop() {
+            board.undo_move(&m);
+        }
+        assert_eq!(board.zobrist_value, zobrist_value);
+        assert_eq!(board.zobrist_value_lock, zobrist_value_lock);
+        assert_eq!(board.turn, Player::Red);
+    }
+
+    #[test]
+    fn test_captured_pieces() {
+        let mut board = Board::init();
+        let moves = [
+            ("h2", "e2"),
+            ("h9", "g7"),
+            // 炮打中卒
+            ("e2", "e6"),
+            ("g6", "g5"),
+            // 炮打马
+            ("b2", "b9"),
+        ];
+        let mut played = vec![];
+        for (from, to) in moves {
+            let m = Move {
+                player: board.turn,
+                from: from.into(),
+                to: to.into(),
+                chess: board.chess_at(from.into()),
+                capture: board.chess_at(to.into()),
+            };
+            board.do_move(&m);
+            played.push(m);
+        }
+        assert_eq!(
+            board.captured_pieces(),
+            (vec![], vec![ChessType::Pawn, ChessType::Knight])
+        );
+        board.undo_move(
+            &played
+                .pop()
+                .unwrap(),
+        );
+        assert_eq!(board.captured_pieces(), (vec![], vec![ChessType::Pawn]));
+    }
+
+    #[test]
+    fn test_analyze() {
+        let mut board = Board::init();
+        board.records = Arc::new(TranspositionTable::new(RECORD_SIZE));
+        // 用节点数限制代替界面发来的停止命令
+        board
+            .config
+            .node_limit = Some(100000);
+        let mut depths = vec![];
+        let (_, best_move) = board.analyze(|depth, _, pv| {
+            assert!(!pv.is_empty());
+            depths.push(depth);
         });
-        for i in 0..10_000 {
-            board.evaluate(Player::Red);
+        assert!(depths.len() >= 2);
+        assert!(depths
+            .windows(2)
+            .all(|w| w[0] <= w[1]));
+        let best_move = best_move.unwrap();
+        let pv = board.principal_variation(&best_move);
+        assert_eq!(pv[0], best_move);
+        // 主要变例走完之后局面恢复原样
+        assert_eq!(board.to_fen(), Board::init().to_fen());
+        // 节点数上限对每次搜索分别计算，用完了上限的棋盘再搜索照样能给出着法
+        assert!(board.nodes >= 100000);
+        let result = board.iterative_deepening(3);
+        assert!(result
+            .best_move
+            .is_some());
+        assert!(result.nodes < 100000);
+        assert!(board
+            .suggest_move(2)
+            .is_some());
+        assert!(board
+            .alpha_beta_pvs(2, MIN, MAX)
+            .1
+            .is_some());
+    }
+
+    #[test]
+    fn test_is_attacked() {
+        let board = Board::from_fen("3k5/9/2r6/9/3p5/9/2P1N4/4C4/9/4K4 w - - 0 1");
+        // 红炮隔着红马攻击e7
+        assert!(board.is_attacked("e7".into(), Player::Red));
+        assert!(!board.is_attacked("e3".into(), Player::Red));
+        // 马腿e2有炮，马跳不到d1，马腿e4没有棋子可以跳到f5
+        assert!(!board.is_attacked("d1".into(), Player::Red));
+        assert!(board.is_attacked("f5".into(), Player::Red));
+        assert!(board.is_attacked("c4".into(), Player::Red));
+        // 帅只能攻击九宫格内相邻的格子
+        assert!(board.is_attacked("e1".into(), Player::Red));
+        assert!(!board.is_attacked("e2".into(), Player::Red));
+        // 黑车被c3的红兵挡住
+        assert!(board.is_attacked("c5".into(), Player::Black));
+        assert!(board.is_attacked("c3".into(), Player::Black));
+        assert!(!board.is_attacked("c2".into(), Player::Black));
+        // 没过河的卒不能横着攻击
+        assert!(board.is_attacked("d4".into(), Player::Black));
+        assert!(!board.is_attacked("e5".into(), Player::Black));
+    }
+
+    #[test]
+    fn test_repetition() {
+        let mut board = Board::from_fen("3ak4/9/4b4/9/9/9/9/4B4/4A4/3K1R3 w - - 0 1");
+        let mut moves = vec![];
+        let mut buf = [0; 4];
+        for _ in 0..300 {
+            // 随机走不吃子的着法，查表和遍历历史的结果应该一致
+            let candidates = board
+                .generate_move(false)
+                .into_iter()
+                .filter(|m| m.capture == Chess::None && board.is_move_legal_cloned(m))
+                .collect::<Vec<_>>();
+            if candidates.is_empty() {
+                break;
+            }
+            getrandom(&mut buf).unwrap();
+            let m = candidates[u32::from_be_bytes(buf) as usize % candidates.len()].clone();
+            board.do_move(&m);
+            moves.push(m);
+            assert_eq!(board.is_repetition(), board.rep_status() > 0);
         }
-        assert_eq!(board.evaluate(Player::Red), 7);
+        while let Some(m) = moves.pop() {
+            board.undo_move(&m);
+            assert_eq!(board.is_repetition(), board.rep_status() > 0);
+        }
+        assert!(board
+            .repetitions
+            .is_empty());
+
+        // 来回走马，第4步后回到初始局面
+        let mut board = Board::init();
+        for m in ["h0g2", "h9g7", "g2h0", "g7h9"] {
+            let (from, to) = (m[..2].into(), m[2..].into());
+            let m = Move {
+                player: board.turn,
+                from,
+                to,
+                chess: board.chess_at(from),
+                capture: Chess::None,
+            };
+            assert!(!board.is_repetition());
+            board.do_move(&m);
+        }
+        assert!(board.is_repetition());
+        assert_eq!(board.rep_status(), 1);
     }
 
     #[test]
-    fn test_alpha_beta_pvs() {
-        println!("{:?}", Board::init().alpha_beta_pvs(1, MIN, MAX));
-        // println!("{:?}", Board::init().alpha_beta_pvs(2, MIN, MAX));
-        // println!("{:?}", Board::init().alpha_beta_pvs(3, MIN, MAX));
-        // println!("{:?}", Board::init().alpha_beta_pvs(4, MIN, MAX));
-        // let mut board = Board::init();
-        // let rst = board.minimax(5, Player::Red, i32::MIN, i32::MAX);
-        // let counter = board.counter;
-        // println!("{} \n {:?}", counter, rst); // 跳马
-        //                                       /* */
-        // println!("{:?}", Board::init().alpha_beta_pvs(6, MIN, MAX)); // 跳马
+    fn test_contempt() {
+        // 黑方多一个卒，红方回到之前的局面就是和棋
+        // distance是开局以来的步数，根节点在第几步都一样
+        let search = |contempt, distance| {
+            let mut board = Board::from_fen("3k5/9/9/9/9/4p4/9/9/9/4K4 w - - 0 1");
+            for m in ["e0f0", "d9e9", "f0e0", "e9d9"] {
+                let (from, to) = (m[..2].into(), m[2..].into());
+                let m = Move {
+                    player: board.turn,
+                    from,
+                    to,
+                    chess: board.chess_at(from),
+                    capture: Chess::None,
+                };
+                board.do_move(&m);
+            }
+            board.distance = distance;
+            board
+                .config
+                .contempt = contempt;
+            board.alpha_beta_pvs(3, MIN, MAX)
+        };
+        for distance in [0, 5] {
+            let (value, m) = search(0, distance);
+            assert_eq!(value, 0);
+            assert_eq!(m.unwrap().to, "f0".into());
+            // 和棋减分太多时，宁可走别的着法也不重复局面
+            let (value, m) = search(100, distance);
+            assert!(value > -100);
+            assert_ne!(m.unwrap().to, "f0".into());
+        }
+    }
+
+    #[test]
+    fn test_max_distance() {
+        // 双方只剩帅仕，谁也赢不了
+        let search = |max_distance| {
+            let mut board = Board::from_fen("3aka3/9/9/9/9/9/9/9/9/3AKA3 w - - 0 1");
+            board
+                .config
+                .max_distance = max_distance;
+            let result = board.alpha_beta_pvs(6, MIN, MAX);
+            assert_eq!(board.distance, 0);
+            (result, board.nodes)
+        };
+        let ((value, m), nodes) = search(2);
+        assert_eq!(value, 0);
+        assert!(m.is_some());
+        let (_, full_nodes) = search(2 * MAX_DEPTH);
+        assert!(nodes < full_nodes);
+
+        // 对局已经走了很多步，离根节点的步数没有超过上限，照常搜索
+        let mut board = Board::init();
+        board.distance = 2 * MAX_DEPTH;
+        let m = board
+            .suggest_move(3)
+            .unwrap();
+        assert!(board.is_move_legal(&m));
+        assert_eq!(board.distance, 2 * MAX_DEPTH);
+        // 静态搜索也照常吃子，红车白吃黑车
+        let mut board = Board::from_fen("3k5/9/9/9/9/9/9/9/r8/R3K4 w - - 0 1");
+        let stand_pat = board.evaluate(Player::Red);
+        board.distance = 2 * MAX_DEPTH;
+        assert!(board.quies(MIN, MAX) > stand_pat + 100);
+    }
+
+    #[test]
+    fn test_history_aging() {
+        let mut board = Board::init();
+        let m = board
+            .moves_of_type(ChessType::Cannon)
+            .remove(0);
+        board.update_history(&m, 4);
+        assert_eq!(board.history_score(&m), 16);
+        board.age_history();
+        assert_eq!(board.history_score(&m), 8);
+        // 分数累加到上限后不再增加
+        for _ in 0..100000 {
+            board.update_history(&m, MAX_DEPTH);
+        }
+        assert_eq!(board.history_score(&m), HISTORY_MAX);
+        board.age_history();
+        assert_eq!(board.history_score(&m), HISTORY_MAX / 2);
+    }
+
+    #[test]
+    fn test_search_result() {
+        let mut board = Board::from_fen("3ak4/9/9/9/9/9/2n6/9/4C4/4K4 w - - 0 1");
+        board.records = Arc::new(TranspositionTable::new(RECORD_SIZE));
+        let result = board.iterative_deepening(4);
+        assert!(result.nodes > 0);
+        assert!(result.gen_nodes > 0);
+        assert_eq!(result.depth_reached, 4);
+        assert_eq!(
+            result.pv[0],
+            result
+                .best_move
+                .unwrap()
+        );
+        // 统计的是这次搜索的量，不包括之前的搜索
+        let nodes = board.nodes;
+        let result = board.iterative_deepening(2);
+        assert_eq!(result.depth_reached, 2);
+        assert_eq!(result.nodes, board.nodes - nodes);
+    }
+
+    #[test]
+    fn test_search_deadline() {
+        let mut board = Board::init();
+        board.records = Arc::new(TranspositionTable::new(RECORD_SIZE));
+        let start = Instant::now();
+        board.deadline = Some(start + Duration::from_millis(50));
+        let result = board.iterative_deepening(MAX_DEPTH);
+        // 超时后在这一层中间就停下，不会等整层搜完
+        assert!(start.elapsed() < Duration::from_secs(2));
+        assert!(result.depth_reached >= 3 && result.depth_reached < MAX_DEPTH);
+        assert!(board.is_move_legal(
+            &result
+                .best_move
+                .unwrap()
+        ));
+        // 截止时间只对这一次搜索有效
+        assert!(board
+            .deadline
+            .is_none());
+        assert!(!board.is_stopped());
+        assert_eq!(
+            board
+                .iterative_deepening(4)
+                .depth_reached,
+            4
+        );
+    }
+
+    #[test]
+    fn test_threefold_repetition() {
+        let mut board = Board::init();
+        let cycle = ["h0g2", "h9g7", "g2h0", "g7h9"];
+        board
+            .play_moves(&cycle)
+            .unwrap();
+        assert!(!board.is_threefold_repetition());
+        board
+            .play_moves(&cycle[..3])
+            .unwrap();
+        assert!(!board.is_threefold_repetition());
+        board
+            .play_moves(&cycle[3..])
+            .unwrap();
+        assert!(board.is_threefold_repetition());
+        assert_eq!(board.game_status(), GameStatus::Draw);
+        // 吃子之前的局面不算
+        let mut board = Board::init();
+        board
+            .play_moves(&["b2b9", "a9b9"])
+            .unwrap();
+        board
+            .play_moves(&cycle)
+            .unwrap();
+        board
+            .play_moves(&cycle)
+            .unwrap();
+        assert!(board.is_threefold_repetition());
+        board.undo_last();
+        assert!(!board.is_threefold_repetition());
+    }
+
+    #[test]
+    fn test_pick_near_best() {
+        // 开局时有好几个分数接近的着法，多选几次不会总是同一个
+        let mut moves = vec![];
+        for _ in 0..20 {
+            let m = Board::init()
+                .pick_near_best(2, 3, 20)
+                .unwrap();
+            if !moves.contains(&m) {
+                moves.push(m);
+            }
+        }
+        assert!(moves.len() > 1);
+        assert!(moves.len() <= 3);
+        // 分数差为0时只会选最好的着法
+        let mut board = Board::from_fen("4k4/9/9/9/9/9/9/9/4r4/3KR4 w - - 0 1");
+        let m = board
+            .pick_near_best(2, 3, 0)
+            .unwrap();
+        assert_eq!(m.to, "e1".into());
+    }
+
+    #[test]
+    fn test_quies_in_check() {
+        // 黑方多一个车，但是被双车将死，不能按局面分站着不动
+        let mut board = Board::from_fen("R2k5/8R/9/9/3rrr3/9/9/9/9/4K4 b - - 0 1");
+        assert!(board.evaluate(Player::Black) > 0);
+        assert_eq!(board.quies(MIN, MAX), MIN);
+        // 去掉一个车后黑将可以躲开，不算被将死
+        let mut board = Board::from_fen("R2k5/9/9/9/3rrr3/9/9/9/9/4K4 b - - 0 1");
+        assert!(board.quies(MIN, MAX) > KILL);
+    }
+
+    #[test]
+    fn test_last_move_and_ply() {
+        let mut board = Board::init();
+        assert_eq!(board.last_move(), None);
+        assert_eq!(board.ply(), 0);
+        board
+            .play_moves(&["h2e2", "h9g7", "h0g2"])
+            .unwrap();
+        assert_eq!(board.ply(), 3);
+        let m = board
+            .last_move()
+            .unwrap();
+        assert_eq!(m.from, "h0".into());
+        assert_eq!(m.to, "g2".into());
+        assert_eq!(m.player, Player::Red);
+        board.undo_move(&m);
+        assert_eq!(board.ply(), 2);
+        assert_eq!(
+            board
+                .last_move()
+                .unwrap()
+                .to,
+            "g7".into()
+        );
+    }
+
+    #[test]
+    fn test_goto_ply() {
+        let init = Board::init();
+        let mut board = Board::init();
+        board
+            .play_moves(&["h2e2", "h9g7", "h0g2", "i9h9", "e2e6", "c6c5"])
+            .unwrap();
+        let fen = board.to_fen();
+        assert!(!board.goto_ply(7));
+        assert_eq!(board.to_fen(), fen);
+        assert!(board.goto_ply(2));
+        assert_eq!(board.ply(), 2);
+        assert!(board.goto_ply(0));
+        assert_eq!(board.chesses, init.chesses);
+        assert_eq!(board.zobrist_value, init.zobrist_value);
+        assert_eq!(board.zobrist_value_lock, init.zobrist_value_lock);
+        assert_eq!(board.turn, init.turn);
+        assert_eq!(board.evaluate(Player::Red), init.evaluate(Player::Red));
+        // 悔掉的棋都可以重做
+        while board
+            .redo_last()
+            .is_some()
+        {}
+        assert_eq!(board.to_fen(), fen);
+    }
+
+    #[test]
+    fn test_undo_to_human_turn() {
+        // 人执红，电脑刚应了一步，悔掉两步
+        let mut board = Board::init();
+        board
+            .play_moves(&["h2e2", "h9g7", "h0g2", "i9h9"])
+            .unwrap();
+        assert!(board.undo_to_human_turn(Player::Red));
+        assert_eq!(board.ply(), 2);
+        assert_eq!(board.turn, Player::Red);
+        // 电脑还没应着，只悔掉人走的一步
+        board
+            .play_moves(&["h0g2"])
+            .unwrap();
+        assert!(board.undo_to_human_turn(Player::Red));
+        assert_eq!(board.ply(), 2);
+        assert!(board.undo_to_human_turn(Player::Red));
+        assert_eq!(board.ply(), 0);
+        assert!(!board.undo_to_human_turn(Player::Red));
+
+        // 人执黑，电脑先走
+        let mut board = Board::init();
+        board
+            .play_moves(&["h2e2"])
+            .unwrap();
+        assert!(!board.undo_to_human_turn(Player::Black));
+        assert_eq!(board.ply(), 1);
+        board
+            .play_moves(&["h9g7", "h0g2"])
+            .unwrap();
+        assert!(board.undo_to_human_turn(Player::Black));
+        assert_eq!(board.ply(), 1);
+        assert_eq!(board.turn, Player::Black);
+        board
+            .play_moves(&["h9g7"])
+            .unwrap();
+        assert!(board.undo_to_human_turn(Player::Black));
+        assert_eq!(board.ply(), 1);
+        assert_eq!(board.to_fen(), {
+            let mut b = Board::init();
+            b.play_moves(&["h2e2"])
+                .unwrap();
+            b.to_fen()
+        });
+    }
+
+    #[test]
+    fn test_quies_checks() {
+        // 马不吃子将军，同时捉车，黑将躲开后马吃车
+        let fen = "4k4/9/9/1r7/2N6/9/9/9/4A4/4K4 w - - 0 1";
+        let (off, _) = quies_with(fen, MIN, MAX, |c| c.quies_checks = false);
+        let (on, _) = quies_with(fen, MIN, MAX, |c| c.quies_checks = true);
+        let stand_pat = Board::from_fen(fen).evaluate(Player::Red);
+        assert_eq!(off, stand_pat);
+        assert!(stand_pat < 0);
+        assert!(on > 0);
+    }
+
+    #[test]
+    fn test_result_token() {
+        // 黑方被将死
+        let mut board = Board::from_fen("3k5/9/9/9/9/9/9/9/9/3RK4 b - - 0 1");
+        assert_eq!(board.game_status(), GameStatus::RedWin);
+        assert_eq!(board.result_token(), "1-0");
+        // 红方被将死
+        let mut board = board.flipped();
+        assert_eq!(board.game_status(), GameStatus::BlackWin);
+        assert_eq!(board.result_token(), "0-1");
+        // 双方都没有进攻子力
+        let mut board = Board::from_fen("4k4/9/9/9/9/9/9/9/9/3K5 w - - 0 1");
+        assert_eq!(board.game_status(), GameStatus::Draw);
+        assert_eq!(board.result_token(), "1/2-1/2");
+        let mut board = Board::init();
+        assert_eq!(board.game_status(), GameStatus::Playing);
+        assert_eq!(board.result_token(), "*");
+    }
+
+    #[test]
+    fn test_incremental_phase() {
+        let full_phase = |board: &Board| {
+            let material: i32 = board
+                .pieces()
+                .map(|(_, chess)| {
+                    board
+                        .eval_params
+                        .phase_weight(
+                            chess
+                                .chess_type()
+                                .unwrap(),
+                        )
+                })
+                .sum();
+            (material * TOTAL_PHASE / OPENING_PHASE_MATERIAL).min(TOTAL_PHASE)
+        };
+        let mut board = Board::from_fen(
+            "r1ba1a3/4kn3/2n1b4/pNp1p1p1p/4c4/6P2/P1P2R2P/1CcC5/9/2BAKAB2 w - - 0 1",
+        );
+        // 优先吃子，走一阵再悔几步，每一步都和重新计算的结果比较
+        let mut seed = 7u64;
+        let mut played = vec![];
+        for i in 0..200 {
+            if i % 5 == 4 {
+                if let Some(m) = played.pop() {
+                    board.undo_move(&m);
+                }
+            } else {
+                let mut moves = board.generate_move(false);
+                moves.retain(|m| board.is_king_safe_after(m));
+                if moves.is_empty() {
+                    break;
+                }
+                let captures = moves
+                    .iter()
+                    .filter(|m| m.capture != Chess::None)
+                    .cloned()
+                    .collect::<Vec<_>>();
+                if !captures.is_empty() {
+                    moves = captures;
+                }
+                seed = seed
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                let m = moves[(seed >> 33) as usize % moves.len()].clone();
+                board.do_move(&m);
+                played.push(m);
+            }
+            assert_eq!(board.piece_counts, board.count_pieces());
+            assert_eq!(board.phase(), full_phase(&board));
+        }
+        assert!(board.phase() < TOTAL_PHASE);
+        while let Some(m) = played.pop() {
+            board.undo_move(&m);
+        }
+        assert_eq!(board.phase(), full_phase(&board));
+    }
+
+    #[test]
+    fn test_pinned_pieces() {
+        let pinned = |fen: &str, player: Player| {
+            let mut pinned = Board::from_fen(fen).pinned_pieces(player);
+            pinned.sort_by_key(|p| (p.row, p.col));
+            pinned
+        };
+        // 车牵制
+        let fen = "3k5/9/4r4/9/9/9/4R4/9/9/4K4 w - - 0 1";
+        assert_eq!(pinned(fen, Player::Red), vec!["e3".into()]);
+        assert!(pinned(fen, Player::Black).is_empty());
+        // 车和将之间隔了两个子，都不算被牵制
+        let fen = "3k5/9/4r4/9/9/4P4/4R4/9/9/4K4 w - - 0 1";
+        assert!(pinned(fen, Player::Red).is_empty());
+        // 炮和将之间的两个子都是己方的，走开哪个都会被将军
+        let fen = "3k5/9/9/4c4/9/4P4/9/4N4/9/4K4 w - - 0 1";
+        assert_eq!(pinned(fen, Player::Red), vec!["e4".into(), "e2".into()]);
+        // 另一个是对方的子，只有己方的子被牵制
+        let fen = "3k5/9/9/4c4/9/4p4/9/4N4/9/4K4 w - - 0 1";
+        assert_eq!(pinned(fen, Player::Red), vec!["e2".into()]);
+        // 炮隔一个子已经在将军，炮架不算被牵制
+        let fen = "3k5/9/9/4c4/9/9/9/4N4/9/4K4 w - - 0 1";
+        assert!(pinned(fen, Player::Red).is_empty());
+        // 横向的车牵制和将帅对脸
+        let fen = "9/4k4/9/4n4/9/9/9/9/9/3RKA2r w - - 0 1";
+        assert_eq!(pinned(fen, Player::Red), vec!["f0".into()]);
+        assert_eq!(pinned(fen, Player::Black), vec!["e6".into()]);
+    }
+
+    #[test]
+    fn test_clone_position() {
+        let mut board = Board::init();
+        board.records = Arc::new(TranspositionTable::new(RECORD_SIZE));
+        board
+            .play_moves(&["h2e2", "h9g7", "h0g2", "g7h9", "g2h0"])
+            .unwrap();
+        board.update_history(
+            &board
+                .last_move()
+                .unwrap(),
+            4,
+        );
+        let light = board.clone_position();
+        assert!(light
+            .records
+            .is_empty());
+        assert!(light
+            .history_table
+            .iter()
+            .all(|&v| v == 0));
+        assert_eq!(light.to_fen(), board.to_fen());
+        assert_eq!(light.move_history, board.move_history);
+        assert_eq!(light.zobrist_value, board.zobrist_value);
+        assert_eq!(light.rep_status(), board.rep_status());
+        // 停止标志不共享
+        board
+            .stop
+            .store(true, Ordering::Relaxed);
+        assert!(!light.is_stopped());
+        board
+            .stop
+            .store(false, Ordering::Relaxed);
+
+        // 搜索结果和完整复制的棋盘一样，都从空的置换表开始
+        let mut full = board.clone();
+        full.records = Arc::new(TranspositionTable::new(RECORD_SIZE));
+        full.history_table = vec![0; HISTORY_SIZE];
+        let mut light = light;
+        light.records = Arc::new(TranspositionTable::new(RECORD_SIZE));
+        assert_eq!(
+            light
+                .iterative_deepening(4)
+                .best_move,
+            full.iterative_deepening(4)
+                .best_move
+        );
+    }
+
+    #[test]
+    fn test_sync_position() {
+        let mut game = Board::init();
+        let mut search = Board::init();
+        search.records = Arc::new(TranspositionTable::new(RECORD_SIZE));
+        search.sync_position(&game);
+        let m = search
+            .suggest_move(5)
+            .unwrap();
+        game.play_move(&m);
+        let reply = game
+            .generate_move(false)
+            .into_iter()
+            .find(|m| game.is_move_legal(m))
+            .unwrap();
+        game.play_move(&reply);
+
+        // 同步后局面和走棋历史跟实际对局一致，置换表和历史表保留下来
+        search.sync_position(&game);
+        assert_eq!(search.to_fen(), game.to_fen());
+        assert_eq!(search.move_history, game.move_history);
+        assert_eq!(search.distance, game.distance);
+        assert_eq!(search.zobrist_value, game.zobrist_value);
+        assert!(search
+            .history_table
+            .iter()
+            .any(|&v| v > 0));
+        assert_eq!(search.nodes, 0);
+
+        // 从相关局面再搜一次，用到上次留下的置换表，节点数比新的置换表少
+        let mut fresh = game.clone_position();
+        fresh.records = Arc::new(TranspositionTable::new(RECORD_SIZE));
+        fresh.suggest_move(5);
+        search.suggest_move(5);
+        assert!(search.nodes < fresh.nodes);
+    }
+
+    #[test]
+    fn test_checkers() {
+        assert!(Board::init()
+            .checkers(Player::Red)
+            .is_empty());
+        // 车将军
+        let board = Board::from_fen("4k4/9/9/9/9/9/9/9/4R4/3K5 b - - 0 1");
+        assert_eq!(board.checkers(Player::Black), vec!["e1".into()]);
+        assert!(board
+            .checkers(Player::Red)
+            .is_empty());
+        // 车马双将
+        let board = Board::from_fen("4k4/9/3N5/9/9/9/9/9/4R4/3K5 b - - 0 1");
+        let mut checkers = board.checkers(Player::Black);
+        checkers.sort_by_key(|p| (p.row, p.col));
+        assert_eq!(checkers, vec!["d7".into(), "e1".into()]);
+        // 将帅对脸
+        let board = Board::from_fen("3k5/9/9/9/9/9/9/9/9/3K5 b - - 0 1");
+        assert_eq!(board.checkers(Player::Black), vec!["d0".into()]);
+        // 炮隔着兵将军，兵挡住了将帅对脸
+        let board = Board::from_fen("3k5/9/3P5/9/3C5/9/9/9/9/3K5 b - - 0 1");
+        assert_eq!(board.checkers(Player::Black), vec!["d5".into()]);
+    }
+
+    #[test]
+    fn test_attackers_of() {
+        let board = Board::from_fen("5k3/9/4C4/4p4/R3p3C/3pP4/3NPN3/4C4/9/3K5 w - - 0 1");
+        // 炮隔一个子攻击，车直线攻击，兵向前攻击，马腿没被挡住的马；
+        // 没有炮架和隔了两个子的炮、马腿被挡住的马都不算
+        let mut attackers = board.attackers_of("e5".into(), Player::Red);
+        attackers.sort_by_key(|(p, _)| (p.row, p.col));
+        assert_eq!(
+            attackers,
+            vec![
+                ("e7".into(), ChessType::Cannon),
+                ("a5".into(), ChessType::Rook),
+                ("e4".into(), ChessType::Pawn),
+                ("f3".into(), ChessType::Knight),
+            ]
+        );
+        // 过河的兵可以横着攻击，没过河的只能向前
+        let mut attackers = board.attackers_of("e4".into(), Player::Black);
+        attackers.sort_by_key(|(p, _)| (p.row, p.col));
+        assert_eq!(
+            attackers,
+            vec![
+                ("e5".into(), ChessType::Pawn),
+                ("d4".into(), ChessType::Pawn)
+            ]
+        );
+        assert!(board
+            .attackers_of("e3".into(), Player::Black)
+            .is_empty());
+    }
+
+    #[test]
+    fn test_position_index() {
+        for index in 0..BOARD_SQUARES {
+            assert_eq!(Position::from_index(index).to_index(), index);
+        }
+        for row in 0..BOARD_HEIGHT {
+            for col in 0..BOARD_WIDTH {
+                let pos = Position::new(row, col);
+                assert_eq!(Position::from_index(pos.to_index()), pos);
+            }
+        }
+        assert_eq!(Position::from("a9").to_index(), 0);
+        assert_eq!(Position::from("i0").to_index(), BOARD_SQUARES - 1);
+    }
+
+    #[test]
+    fn test_position_offset() {
+        // 四个角往外走都出了棋盘
+        for (corner, outward) in [
+            ("a9", [(-1, 0), (0, -1), (-2, 1)]),
+            ("i9", [(-1, 0), (0, 1), (-1, -2)]),
+            ("a0", [(1, 0), (0, -1), (2, 1)]),
+            ("i0", [(1, 0), (0, 1), (1, -2)]),
+        ] {
+            let pos = Position::from(corner);
+            assert_eq!(pos.offset(0, 0), Some(pos));
+            for (dr, dc) in outward {
+                assert_eq!(pos.offset(dr, dc), None);
+            }
+        }
+        assert_eq!(
+            Position::from("a9").offset(2, 1),
+            Some(Position::from("b7"))
+        );
+        assert_eq!(
+            Position::from("i0").offset(-1, -2),
+            Some(Position::from("g1"))
+        );
+        assert_eq!(
+            Position::from("a0").offset(-9, 8),
+            Some(Position::from("i9"))
+        );
+        assert_eq!(Position::from("a0").offset(-10, 0), None);
+
+        // 角上的兵和马照样能找到
+        let board = Board::from_fen("3k5/9/6N2/9/9/9/9/9/p8/4K4 w - - 0 1");
+        assert_eq!(
+            board.attackers_of(Position::from("a0"), Player::Black),
+            vec![(Position::from("a1"), ChessType::Pawn)]
+        );
+        assert_eq!(
+            board.attackers_of(Position::from("i8"), Player::Red),
+            vec![(Position::from("g7"), ChessType::Knight)]
+        );
+        assert!(board
+            .attackers_of(Position::from("i9"), Player::Red)
+            .is_empty());
+    }
+
+    #[test]
+    fn test_pieces() {
+        let board = Board::init();
+        assert_eq!(
+            board
+                .pieces()
+                .count(),
+            32
+        );
+        assert_eq!(board.piece_count(Player::Red), 16);
+        assert_eq!(board.piece_count(Player::Black), 16);
+        let board = Board::from_fen("4k4/9/9/9/9/9/9/9/4R4/3K5 w - - 0 1");
+        assert_eq!(
+            board
+                .pieces()
+                .collect::<Vec<_>>(),
+            vec![
+                ("e9".into(), Chess::Black(ChessType::King)),
+                ("e1".into(), Chess::Red(ChessType::Rook)),
+                ("d0".into(), Chess::Red(ChessType::King)),
+            ]
+        );
+        assert_eq!(board.piece_count(Player::Red), 2);
+        assert_eq!(board.piece_count(Player::Black), 1);
+    }
+
+    #[test]
+    fn test_in_check_cache() {
+        let mut buf = [0; 4];
+        for _ in 0..10 {
+            let mut board = Board::init();
+            let mut moves = vec![];
+            for _ in 0..100 {
+                let candidates = board
+                    .generate_move(false)
+                    .into_iter()
+                    .filter(|m| board.is_move_legal_cloned(m))
+                    .collect::<Vec<_>>();
+                if candidates.is_empty() {
+                    break;
+                }
+                getrandom(&mut buf).unwrap();
+                let m = candidates[u32::from_be_bytes(buf) as usize % candidates.len()].clone();
+                board.do_move(&m);
+                moves.push(m);
+                assert_eq!(board.in_check(), board.is_checked(board.turn));
+            }
+            while let Some(m) = moves.pop() {
+                board.undo_move(&m);
+                assert_eq!(board.in_check(), board.is_checked(board.turn));
+            }
+        }
+        let board = Board::from_fen("4k4/9/9/9/9/9/9/9/4R4/3K5 b - - 0 1");
+        assert!(board.in_check());
+        // 双方互换后是红方被将军
+        assert!(board
+            .flipped()
+            .in_check());
+    }
+
+    #[test]
+    fn test_find_mate() {
+        // 两步杀：车进将，黑将只能上去，另一个车再沉底
+        let mut board = Board::from_fen("9/4k4/R8/9/1R7/9/9/9/9/3K5 w - - 0 1");
+        assert_eq!(board.find_mate(2), None);
+        let mate = board
+            .find_mate(5)
+            .unwrap();
+        assert_eq!(mate.len(), 3);
+        assert_eq!(mate[0].player, Player::Red);
+        assert_eq!(mate[2].player, Player::Red);
+        for m in mate.iter() {
+            assert!(board.is_move_legal(m));
+            board.do_move(m);
+        }
+        assert_eq!(board.game_status(), GameStatus::RedWin);
+        // distance是开局以来的步数，对局已经走过几步时一样能找到
+        let mut board = Board::from_fen("9/4k4/R8/9/1R7/9/9/9/9/3K5 w - - 0 1");
+        board.distance = 2;
+        assert_eq!(
+            board
+                .find_mate(5)
+                .map(|mate| mate.len()),
+            Some(3)
+        );
+        assert_eq!(board.distance, 2);
+        // 子力不够时找不到杀棋
+        let mut board = Board::from_fen("3ak4/4a4/9/9/9/9/9/9/4A4/3AK4 w - - 0 1");
+        assert_eq!(board.find_mate(3), None);
+    }
+
+    #[test]
+    fn test_iterative_deepening_mate() {
+        // 两步杀，第3层就找到了，不再往下搜
+        let mut board = Board::from_fen("9/4k4/R8/9/1R7/9/9/9/9/3K5 w - - 0 1");
+        board.records = Arc::new(TranspositionTable::new(RECORD_SIZE));
+        let result = board.iterative_deepening(10);
+        assert_eq!(mate_distance(result.score), Some(3));
+        assert!(result.depth_reached < 10);
+        assert!(board.is_move_legal(
+            &result
+                .best_move
+                .unwrap()
+        ));
+        // 被将死的一方也不再加深
+        board.do_move(&result.pv[0]);
+        let result = board.iterative_deepening(10);
+        assert_eq!(mate_distance(result.score), Some(-2));
+        assert!(result.depth_reached < 10);
+        assert_eq!(mate_distance(100), None);
+    }
+
+    #[test]
+    fn test_mate_after_long_game() {
+        // 对局已经走了150步，杀棋分数只看离根节点的步数，仍然认得出来
+        let mut board = Board::from_fen("9/4k4/R8/9/1R7/9/9/9/9/3K5 w - - 0 1");
+        board.records = Arc::new(TranspositionTable::new(RECORD_SIZE));
+        board.distance = 150;
+        let (value, _) = board.alpha_beta_pvs(5, MIN, MAX);
+        assert_eq!(mate_distance(value), Some(3));
+        assert_eq!(
+            board
+                .find_mate(5)
+                .map(|mate| mate.len()),
+            Some(3)
+        );
+        let result = board.iterative_deepening(10);
+        assert_eq!(mate_distance(result.score), Some(3));
+        assert!(result.depth_reached < 10);
+        // 置换表里的杀棋分数换到别的步数取出来，离将死的步数不变
+        board.distance = 0;
+        let (value, _) = board.alpha_beta_pvs(5, MIN, MAX);
+        assert_eq!(mate_distance(value), Some(3));
+        assert_eq!(board.distance, 0);
+    }
+
+    #[test]
+    fn test_root_jitter() {
+        let search = |fen: &str, jitter: i32, seed: u64| {
+            let mut board = Board::from_fen(fen);
+            board.records = Arc::new(TranspositionTable::new(RECORD_SIZE));
+            board
+                .config
+                .root_jitter = jitter;
+            board
+                .config
+                .jitter_seed = seed;
+            board
+                .iterative_deepening(3)
+                .best_move
+                .unwrap()
+        };
+        // 开局时分数接近的着法很多，不同的种子选出不同的着法，同一个种子每次都一样
+        let fen = "rnbakabnr/9/1c5c1/p1p1p1p1p/9/9/P1P1P1P1P/1C5C1/9/RNBAKABNR w - - 0 1";
+        let moves = (0..8)
+            .map(|seed| search(fen, 20, seed))
+            .collect::<Vec<_>>();
+        assert!(moves
+            .iter()
+            .any(|m| *m != moves[0]));
+        assert_eq!(search(fen, 20, 3), moves[3]);
+        // 不加随机分数时和种子无关
+        assert_eq!(search(fen, 0, 1), search(fen, 0, 2));
+        // 车白吃一个车直到杀棋，不会因为随机分数被放过
+        let fen = "4k4/9/9/9/4r4/9/9/9/4R4/3K5 w - - 0 1";
+        for seed in 0..8 {
+            assert_eq!(search(fen, 20, seed).to_string(), "e1e5");
+        }
+    }
+
+    #[test]
+    fn test_suggest_move() {
+        let mut board = Board::init();
+        let m = board
+            .suggest_move(3)
+            .unwrap();
+        assert_eq!(m.player, Player::Red);
+        assert!(board.is_move_legal(&m));
+        // 只是提示，不会走棋
+        assert_eq!(board.to_fen(), Board::init().to_fen());
+    }
+
+    #[test]
+    fn test_perpetual_chase() {
+        // 红车来回捉没有保护的黑炮，黑炮来回躲
+        let mut board = Board::from_fen("4k4/9/c8/8R/9/9/9/9/9/3K5 w - - 0 1");
+        let moves = ["i6i7", "a7a6", "i7i6", "a6a7"];
+        for _ in 0..2 {
+            assert_eq!(board.game_status(), GameStatus::Playing);
+            for m in moves {
+                let (from, to) = (m[..2].into(), m[2..].into());
+                let m = Move {
+                    player: board.turn,
+                    from,
+                    to,
+                    chess: board.chess_at(from),
+                    capture: board.chess_at(to),
+                };
+                board.do_move(&m);
+            }
+        }
+        assert_eq!(board.rep_status(), 2);
+        assert_eq!(board.repetition_verdict(), Some(GameStatus::BlackWin));
+        assert_eq!(board.game_status(), GameStatus::BlackWin);
+        assert_eq!(
+            board
+                .move_history
+                .len(),
+            8
+        );
+
+        // 双方都只是来回走马，判和
+        let mut board = Board::init();
+        for m in ["h0g2", "h9g7", "g2h0", "g7h9"] {
+            let (from, to) = (m[..2].into(), m[2..].into());
+            let m = Move {
+                player: board.turn,
+                from,
+                to,
+                chess: board.chess_at(from),
+                capture: Chess::None,
+            };
+            board.do_move(&m);
+        }
+        assert_eq!(board.repetition_verdict(), Some(GameStatus::Draw));
+    }
+
+    #[test]
+    fn test_chase_defended_piece() {
+        // 黑炮有车保护，红车来回捉不算长捉，判和
+        let mut board = Board::from_fen("r3k4/9/c8/8R/9/9/9/9/9/3K5 w - - 0 1");
+        for _ in 0..2 {
+            for m in ["i6i7", "a7a6", "i7i6", "a6a7"] {
+                let (from, to) = (m[..2].into(), m[2..].into());
+                let m = Move {
+                    player: board.turn,
+                    from,
+                    to,
+                    chess: board.chess_at(from),
+                    capture: board.chess_at(to),
+                };
+                board.do_move(&m);
+            }
+        }
+        assert_eq!(board.rep_status(), 2);
+        assert_eq!(board.repetition_verdict(), Some(GameStatus::Draw));
+        assert_eq!(board.game_status(), GameStatus::Draw);
+
+        // 帅捉没有保护的马也不算捉
+        let mut board = Board::from_fen("3k5/9/9/9/9/9/9/9/3n5/4K4 w - - 0 1");
+        let m = Move {
+            player: Player::Red,
+            from: "e0".into(),
+            to: "e1".into(),
+            chess: Chess::Red(ChessType::King),
+            capture: Chess::None,
+        };
+        board.do_move(&m);
+        assert!(board
+            .chased_pieces(&m)
+            .is_empty());
+    }
+
+    #[test]
+    fn test_undo_and_redo() {
+        let mut board = Board::init();
+        for (from, to) in [("h2", "e2"), ("h9", "g7"), ("e2", "e6"), ("i9", "h9")] {
+            let m = Move {
+                player: board.turn,
+                from: from.into(),
+                to: to.into(),
+                chess: board.chess_at(from.into()),
+                capture: board.chess_at(to.into()),
+            };
+            board.play_move(&m);
+        }
+        let chesses = board.chesses;
+        let zobrist_value = board.zobrist_value;
+        assert!(board
+            .undo_last()
+            .is_some());
+        assert!(board
+            .undo_last()
+            .is_some());
+        assert_ne!(board.zobrist_value, zobrist_value);
+        assert!(board
+            .redo_last()
+            .is_some());
+        assert!(board
+            .redo_last()
+            .is_some());
+        assert!(board
+            .redo_last()
+            .is_none());
+        assert_eq!(board.chesses, chesses);
+        assert_eq!(board.zobrist_value, zobrist_value);
+        assert_eq!(board.turn, Player::Red);
+
+        // 走了新的着法后不能再重做
+        board.undo_last();
+        let m = board
+            .generate_move(false)
+            .pop()
+            .unwrap();
+        board.play_move(&m);
+        assert!(board
+            .redo_last()
+            .is_none());
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let mut board = Board::init();
+        board.apply_move(&Move {
+            player: Player::Red,
+            from: Position { row: 9, col: 8 },
+            to: Position { row: 7, col: 8 },
+            chess: Chess::Red(ChessType::Rook),
+            capture: Chess::None,
+        });
+        for i in 0..10_000 {
+            board.evaluate(Player::Red);
+        }
+        assert_eq!(board.evaluate(Player::Red), 7);
+    }
+
+    #[test]
+    fn test_material() {
+        let board = Board::init();
+        assert_eq!(board.material_balance(), 0);
+        assert_eq!(
+            board.material(Player::Red),
+            2 * (90 + 40 + 45 + 20 + 20) + 5 * 10
+        );
+        // 红方少一个车
+        let board = Board::from_fen(
+            "rnbakabnr/9/1c5c1/p1p1p1p1p/9/9/P1P1P1P1P/1C5C1/9/1NBAKABNR w - - 0 1",
+        );
+        assert_eq!(board.material_balance(), -90);
+        assert!(board.phase() < TOTAL_PHASE);
+    }
+
+    #[test]
+    fn test_try_from_fen() {
+        let fen = "4k4/9/9/9/9/9/9/9/4R4/3K5 b - - 0 1";
+        let mut board = Board::try_from_fen(fen).unwrap();
+        assert_eq!(board.turn, Player::Black);
+        assert_eq!(board.to_fen(), Board::from_fen(fen).to_fen());
+        // 黑将被车将军，走到d9会和红帅对脸，只能走f9
+        let targets = board
+            .generate_move(false)
+            .into_iter()
+            .filter(|m| board.is_move_legal_cloned(m))
+            .map(|m| m.to)
+            .collect::<Vec<_>>();
+        assert_eq!(targets, vec![Position::from("f9")]);
+
+        assert_eq!(
+            Board::try_from_fen("4k4/9/9 w - - 0 1").err(),
+            Some(FenParseError::Rows(3))
+        );
+        assert_eq!(
+            Board::try_from_fen("4k4/9/9/9/9/9/9/9/9/3K4 w - - 0 1").err(),
+            Some(FenParseError::Columns(9, 8))
+        );
+        assert_eq!(
+            Board::try_from_fen("4k4/9/9/9/9/9/9/9/9/3X5 w - - 0 1").err(),
+            Some(FenParseError::Chess('X'))
+        );
+        assert_eq!(
+            Board::try_from_fen("4k4/9/9/9/9/9/9/9/9/3K5 x - - 0 1").err(),
+            Some(FenParseError::Turn("x".to_owned()))
+        );
+        assert_eq!(
+            Board::try_from_fen("9/9/9/9/9/9/9/9/9/3K5 w - - 0 1").err(),
+            Some(FenParseError::King)
+        );
+        // 多余的空格和制表符不影响结果
+        for fen in [
+            "4k4/9/9/9/9/9/9/9/4R4/3K5  b - - 0 1",
+            "4k4/9/9/9/9/9/9/9/4R4/3K5\tb\t-\t-\t0\t1",
+            " 4k4/9/9/9/9/9/9/9/4R4/3K5 b - - 0 1\n",
+        ] {
+            let board = Board::try_from_fen(fen).unwrap();
+            assert_eq!(board.turn, Player::Black);
+            assert_eq!(
+                board.to_fen(),
+                Board::from_fen("4k4/9/9/9/9/9/9/9/4R4/3K5 b - - 0 1").to_fen()
+            );
+        }
+    }
+
+    #[test]
+    fn test_validate_position() {
+        assert_eq!(Board::init().validate_position(), Ok(()));
+        assert_eq!(
+            Board::from_fen("9/9/9/9/9/9/9/9/9/3K5 w - - 0 1").validate_position(),
+            Err(PositionError::KingCount(Player::Black, 0))
+        );
+        assert_eq!(
+            Board::from_fen("4k4/9/9/9/4K4/9/9/9/9/9 b - - 0 1").validate_position(),
+            Err(PositionError::KingOutsidePalace(Player::Red))
+        );
+        // 兵在底线上，兵在没过河时离开了原来的列
+        assert_eq!(
+            Board::from_fen("4k4/9/9/9/9/9/9/9/9/P2K5 w - - 0 1").validate_position(),
+            Err(PositionError::PawnPosition("a0".into()))
+        );
+        assert_eq!(
+            Board::from_fen("4k4/9/9/9/9/9/1P7/9/9/3K5 w - - 0 1").validate_position(),
+            Err(PositionError::PawnPosition("b3".into()))
+        );
+        assert_eq!(
+            Board::from_fen("4k4/9/9/1p7/9/9/9/9/9/3K5 w - - 0 1").validate_position(),
+            Err(PositionError::PawnPosition("b6".into()))
+        );
+        // 过河的兵可以横走
+        assert_eq!(
+            Board::from_fen("4k4/9/1P7/9/9/9/9/9/9/3K5 w - - 0 1").validate_position(),
+            Ok(())
+        );
+        // 轮到红方走时黑将正被将军
+        assert_eq!(
+            Board::from_fen("4k4/9/9/9/9/9/9/9/4R4/3K5 w - - 0 1").validate_position(),
+            Err(PositionError::OpponentInCheck)
+        );
+        assert_eq!(
+            Board::try_from_fen("4k4/9/9/9/9/9/9/9/9/P2K5 w - - 0 1").err(),
+            Some(FenParseError::Position(PositionError::PawnPosition(
+                "a0".into()
+            )))
+        );
+    }
+
+    #[test]
+    fn test_singular_extension() {
+        let search = |fen: &str, depth: i32, singular: bool| {
+            let (result, _) = search_with(fen, depth, |c| {
+                c.singular_extension = singular;
+                // 剃刀剪枝会改变浅层的分数，这里只比较奇异延伸
+                c.razoring = false;
+            });
+            (result.score, result.nodes)
+        };
+        // 延伸唯一的好着法后，4层就能得到6层搜索的分数
+        let fen = "1nbk2b1r/9/7c1/1Cp1p1p1p/9/6P2/2P1P3P/8B/r1c6/R1BAKA1NR w - - 2 9";
+        let (off, _) = search(fen, 4, false);
+        let (on, _) = search(fen, 4, true);
+        let (deeper, _) = search(fen, 6, false);
+        assert_ne!(off, deeper);
+        assert_eq!(on, deeper);
+        // 平稳的局面几乎不增加节点数
+        let fen = "3ak4/4a4/4b4/9/9/9/9/4B4/4A4/3K1R3 w - - 0 1";
+        let (off, off_nodes) = search(fen, 4, false);
+        let (on, on_nodes) = search(fen, 4, true);
+        assert_eq!(on, off);
+        assert!(on_nodes < off_nodes * 3 / 2);
+    }
+
+    #[test]
+    fn test_moves_of_type() {
+        let mut board = Board::init();
+        // 每个炮横走6步、进4步、退1步，再隔子吃马
+        let moves = board.moves_of_type(ChessType::Cannon);
+        assert_eq!(moves.len(), 24);
+        assert!(moves
+            .iter()
+            .all(|m| m.chess == Chess::Red(ChessType::Cannon)));
+        assert_eq!(
+            moves
+                .iter()
+                .filter(|m| m.capture != Chess::None)
+                .count(),
+            2
+        );
+        assert_eq!(
+            board
+                .moves_of_type(ChessType::Rook)
+                .len(),
+            4
+        );
+    }
+
+    #[test]
+    fn test_tapered_evaluate() {
+        assert_eq!(Board::init().phase(), TOTAL_PHASE);
+        // 残局时帅在九宫中间比在底线价值高
+        let back = Board::from_fen("3k5/9/9/9/9/9/9/9/9/4K4 w - - 0 1");
+        let center = Board::from_fen("3k5/9/9/9/9/9/9/9/4K4/9 w - - 0 1");
+        assert_eq!(back.phase(), 0);
+        assert!(center.evaluate(Player::Red) > back.evaluate(Player::Red));
+        // 开局时帅在底线更安全
+        let back = Board::from_fen("rnbakabnr/9/1c5c1/9/9/9/9/1C5C1/9/RNBAKABNR w - - 0 1");
+        let center = Board::from_fen("rnbakabnr/9/1c5c1/9/9/9/9/1C5C1/4K4/RNBA1ABNR w - - 0 1");
+        assert!(center.evaluate(Player::Red) < back.evaluate(Player::Red));
+    }
+
+    #[test]
+    fn test_alpha_beta_pvs() {
+        println!("{:?}", Board::init().alpha_beta_pvs(1, MIN, MAX));
+        // println!("{:?}", Board::init().alpha_beta_pvs(2, MIN, MAX));
+        // println!("{:?}", Board::init().alpha_beta_pvs(3, MIN, MAX));
+        // println!("{:?}", Board::init().alpha_beta_pvs(4, MIN, MAX));
+        // let mut board = Board::init();
+        // let rst = board.minimax(5, Player::Red, i32::MIN, i32::MAX);
+        // let counter = board.counter;
+        // println!("{} \n {:?}", counter, rst); // 跳马
+        //                                       /* */
+        // println!("{:?}", Board::init().alpha_beta_pvs(6, MIN, MAX)); // 跳马
+    }
+
+    #[test]
+    fn test_search_by_difficulty() {
+        let fens = [
+            "4k4/9/9/9/4r4/9/9/4R4/9/3K5 w - - 0 1",
+            "3ak4/9/9/9/9/9/2n6/9/4C4/4K4 w - - 0 1",
+            "4k4/9/9/9/9/2p6/9/2R6/9/3K5 w - - 0 1",
+            // 简单难度只看两步，挑出来的几个着法按困难难度的深度打分都不如最好的着法
+            "1nbak4/4a4/4b4/p5C2/8p/2P6/P3P3P/9/9/2BAKAB2 w - - 3 3",
+        ];
+        let mut easy_total = 0;
+        let mut hard_total = 0;
+        for fen in fens {
+            let mut board = Board::from_fen(fen);
+            // 用困难难度的深度给各着法打分
+            let scores = board.root_move_scores(Difficulty::Hard.max_depth());
+            let score_of = |m: &Move| {
+                scores
+                    .iter()
+                    .find(|(_, sm)| sm == m)
+                    .map(|(v, _)| *v)
+                    .unwrap()
+            };
+            easy_total += score_of(
+                &board
+                    .search_by_difficulty(Difficulty::Easy)
+                    .unwrap(),
+            );
+            hard_total += score_of(
+                &board
+                    .search_by_difficulty(Difficulty::Hard)
+                    .unwrap(),
+            );
+        }
+        assert!(hard_total > easy_total);
+        // 开局局面困难难度搜不完，到了思考时间就中止正在搜的这一层
+        let start = Instant::now();
+        assert!(Board::init()
+            .search_by_difficulty(Difficulty::Hard)
+            .is_some());
+        assert!(start.elapsed() < Difficulty::Hard.movetime() + Duration::from_secs(1));
+        // 困难难度搜得更深，新棋盘上同一局面搜索的节点数比简单和中等难度都多
+        for fen in fens {
+            let searched_nodes = |difficulty| {
+                let mut board = Board::from_fen(fen);
+                board
+                    .search_by_difficulty(difficulty)
+                    .unwrap();
+                board.nodes - board.start_nodes
+            };
+            let easy = searched_nodes(Difficulty::Easy);
+            let medium = searched_nodes(Difficulty::Medium);
+            let hard = searched_nodes(Difficulty::Hard);
+            assert!(easy < hard && medium < hard, "{} {} {}", easy, medium, hard);
+        }
+    }
+
+    #[test]
+    fn test_evaluate_move() {
+        let mut board = Board::from_fen("4k4/9/9/9/4r4/9/9/9/4R4/3K5 w - - 0 1");
+        let (best, _) = board.alpha_beta_pvs(3, MIN, MAX);
+        let capture = board
+            .chinese_to_move("车五进四")
+            .unwrap();
+        assert_eq!(board.evaluate_move(&capture, 3), best);
+        // 车走开，不吃送上门的车，分数比最好的着法低得多
+        let bad = board
+            .chinese_to_move("车五平九")
+            .unwrap();
+        assert!(board.evaluate_move(&bad, 3) < best - 100);
+        assert_eq!(board.distance, 0);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_save_and_load_json() {
+        let mut board = Board::init();
+        for (from, to) in [("h2", "e2"), ("h9", "g7"), ("e2", "e6"), ("i9", "h9")] {
+            let m = Move {
+                player: board.turn,
+                from: from.into(),
+                to: to.into(),
+                chess: board.chess_at(from.into()),
+                capture: board.chess_at(to.into()),
+            };
+            board.do_move(&m);
+        }
+        let json = board
+            .save_to_json()
+            .unwrap();
+        let mut loaded = Board::load_from_json(&json).unwrap();
+        assert_eq!(loaded.chesses, board.chesses);
+        assert_eq!(loaded.turn, board.turn);
+        assert_eq!(loaded.move_history, board.move_history);
+        assert_eq!(loaded.zobrist_value, board.zobrist_value);
+        assert_eq!(loaded.zobrist_value_lock, board.zobrist_value_lock);
+        assert_eq!(loaded.distance, board.distance);
+        assert_eq!(
+            loaded.alpha_beta_pvs(2, MIN, MAX),
+            board.alpha_beta_pvs(2, MIN, MAX)
+        );
+        while let Some(m) = loaded
+            .move_history
+            .last()
+            .cloned()
+        {
+            loaded.undo_move(&m);
+        }
+        assert_eq!(loaded.zobrist_value, Board::init().zobrist_value);
+    }
+
+    #[test]
+    fn test_futility_pruning() {
+        let fens = [
+            "4k4/9/9/9/4r4/9/9/4R4/9/3K5 w - - 0 1",
+            "3ak4/9/9/9/9/9/2n6/9/4C4/4K4 w - - 0 1",
+            "rnbakabnr/9/1c5c1/p1p1p1p1p/9/9/P1P1P1P1P/1C5C1/9/RNBAKABNR w - - 0 1",
+        ];
+        let mut nodes_total = 0;
+        let mut pruned_nodes_total = 0;
+        for fen in fens {
+            let (result, _) = search_with(fen, 3, |c| c.futility_pruning = false);
+            let (pruned, _) = search_with(fen, 3, |c| c.futility_pruning = true);
+            assert_eq!(pruned.best_move, result.best_move);
+            assert_eq!(pruned.score, result.score);
+            assert!(pruned.nodes <= result.nodes);
+            nodes_total += result.nodes;
+            pruned_nodes_total += pruned.nodes;
+        }
+        assert!(pruned_nodes_total < nodes_total);
+    }
+
+    #[test]
+    fn test_razoring() {
+        let search = |fen: &str, razoring: bool| search_with(fen, 4, |c| c.razoring = razoring).0;
+        // 红方少了双车和一个马，明显要输
+        let lost = "rnbakabnr/9/1c5c1/p1p1p1p1p/9/9/P1P1P1P1P/1C5C1/9/4KABN1 b - - 0 1";
+        assert!(search(lost, true).nodes < search(lost, false).nodes);
+
+        // 有战术的局面，剪枝不改变最佳着法
+        let fens = [
+            "4k4/9/9/9/4r4/9/9/4R4/9/3K5 w - - 0 1",
+            "3ak4/9/9/9/9/9/2n6/9/4C4/4K4 w - - 0 1",
+            "r1bakabr1/9/1cn3nc1/p1p1p1p1p/9/9/P1P1P1P1P/1CN3NC1/9/R1BAKABR1 w - - 0 1",
+        ];
+        for fen in fens {
+            assert_eq!(search(fen, true).best_move, search(fen, false).best_move);
+        }
+    }
+
+    #[test]
+    fn test_is_valid_move_for() {
+        let mut board = Board::init();
+        let m = board
+            .chinese_to_move("炮二平五")
+            .unwrap();
+        board.do_move(&m);
+        // 轮到黑方走棋时，红方的着法仍然可以按红方检查
+        let red = Move {
+            player: Player::Red,
+            from: "h0".into(),
+            to: "g2".into(),
+            chess: Chess::Red(ChessType::Knight),
+            capture: Chess::None,
+        };
+        assert!(board.is_valid_move_for(&red, Player::Red));
+        assert!(!board.is_valid_move_for(&red, Player::Black));
+        assert!(!board.is_move_legal(&red));
+        // 着法记录的走棋方和棋子颜色不一致
+        let wrong = Move {
+            player: Player::Black,
+            ..red.clone()
+        };
+        assert!(!board.is_valid_move_for(&wrong, Player::Black));
+        // 兵卒按各自的方向检查
+        let pawn = Move {
+            player: Player::Red,
+            from: "c3".into(),
+            to: "c4".into(),
+            chess: Chess::Red(ChessType::Pawn),
+            capture: Chess::None,
+        };
+        assert!(board.is_valid_move_for(&pawn, Player::Red));
+        let back = Move {
+            from: "c3".into(),
+            to: "c2".into(),
+            ..pawn.clone()
+        };
+        assert!(!board.is_valid_move_for(&back, Player::Red));
+
+        // 置换表里记着红方的着法，轮到黑方时不会当作优先着法
+        let picker = MovePicker::new(&mut board, vec![red], false);
+        assert!(picker
+            .priority
+            .is_empty());
+        picker.finish(&mut board);
+    }
+
+    #[test]
+    fn test_apply_moves() {
+        let mut board = Board::init();
+        let start = board.clone();
+        let mut moves = vec![];
+        for text in ["炮二平五", "马8进7"] {
+            let m = board
+                .chinese_to_move(text)
+                .unwrap();
+            board.do_move(&m);
+            moves.push(m);
+        }
+        // 第三步让红马从h0跳到己方的炮所在的e2，不合法
+        moves.push(Move {
+            player: Player::Red,
+            from: "h0".into(),
+            to: "e2".into(),
+            chess: Chess::Red(ChessType::Knight),
+            capture: Chess::None,
+        });
+        let mut board = start.clone();
+        assert_eq!(board.apply_moves(&moves), Err(2));
+        assert_eq!(board.to_fen(), start.to_fen());
+        assert_eq!(board.zobrist_value, start.zobrist_value);
+        assert_eq!(board.turn, Player::Red);
+        assert!(board
+            .move_history
+            .is_empty());
+        // 全部合法时都走
+        assert_eq!(board.apply_moves(&moves[..2]), Ok(()));
+        assert_eq!(board.turn, Player::Red);
+        assert_ne!(board.zobrist_value, start.zobrist_value);
+    }
+
+    #[test]
+    fn test_is_quiet() {
+        // 黑马没有保护，红车可以吃
+        let mut board = Board::from_fen("3ak4/9/9/9/9/2n6/9/9/2R6/4K4 w - - 0 1");
+        assert!(!board.is_quiet());
+        // 双方的兵卒都没过河，也没有互相顶着，谁也吃不到谁
+        let mut board = Board::from_fen("3ak4/9/9/p1p1p1p1p/9/9/P1P1P1P1P/9/9/4K4 w - - 0 1");
+        assert!(board.is_quiet());
+        // 被将军的局面不平静
+        let mut board = Board::from_fen("3ak4/9/9/9/9/9/9/9/9/3RK4 b - - 0 1");
+        assert!(!board.is_quiet());
+        // 马被车牵制，吃卒后帅被将军，不算合法的吃子
+        let mut board = Board::from_fen("3ak4/9/9/9/4r4/9/5p3/9/4N4/4K4 w - - 0 1");
+        assert!(!board
+            .generate_move(true)
+            .is_empty());
+        assert!(board.is_quiet());
+    }
+
+    #[test]
+    fn test_delta_pruning() {
+        let search = |fen: &str, delta_pruning: bool| {
+            let (result, _) = search_with(fen, 4, |c| c.delta_pruning = delta_pruning);
+            ((result.score, result.best_move), result.nodes)
+        };
+        // 双方有很多兵可以互相吃
+        let fens = [
+            "3akab2/9/4b4/p1p1p1p1p/P1P1P1P1P/9/9/4B4/4A4/3AK1BR1 w - - 0 1",
+            "2bakab2/9/9/1pP1p1P1p/1P2P3P/9/9/9/9/4K4 w - - 0 1",
+            "3ak4/4a4/9/p1p1p1p1p/1P1P1P1P1/9/9/9/9/4K4 w - - 0 1",
+        ];
+        for fen in fens {
+            let (result, nodes) = search(fen, false);
+            let (pruned_result, pruned_nodes) = search(fen, true);
+            assert_eq!(pruned_result, result);
+            assert!(pruned_nodes < nodes);
+        }
+
+        // 白吃一个车，局面分加上车的分数能超过alpha，不能剪掉
+        let fen = "3k5/9/9/9/9/9/9/9/r8/R3K4 w - - 0 1";
+        let quies = |alpha: i32, delta_pruning: bool| {
+            quies_with(fen, alpha, alpha + 1, |c| c.delta_pruning = delta_pruning).0
+        };
+        let v = Board::from_fen(fen).quies(MIN, MAX);
+        assert!(v > 100);
+        for alpha in [v - 100, v - 1] {
+            assert_eq!(quies(alpha, false), alpha + 1);
+            assert_eq!(quies(alpha, true), alpha + 1);
+        }
+        assert_eq!(quies(v, true), v);
+    }
+
+    #[test]
+    fn test_quies_see() {
+        // 红车只能吃被卒保护的卒，吃了就丢车
+        let fen = "3ak4/9/9/9/2p6/2p6/9/9/9/2RK5 w - - 0 1";
+        let (v, nodes) = quies_with(fen, MIN, MAX, |c| c.quies_see = false);
+        let (see_v, see_nodes) = quies_with(fen, MIN, MAX, |c| c.quies_see = true);
+        assert_eq!(see_v, v);
+        assert_eq!(see_nodes, 1);
+        assert!(nodes > see_nodes);
+    }
+
+    #[test]
+    fn test_null_move() {
+        let search = |fen: &str, depth: i32, null_move: bool| {
+            search_with(fen, depth, |c| c.null_move = null_move).0
+        };
+        let fen = "rnbakabnr/9/1c5c1/p1p1p1p1p/9/9/P1P1P1P1P/1C5C1/9/RNBAKABNR w - - 0 1";
+        let off = search(fen, 5, false);
+        let on = search(fen, 5, true);
+        assert_eq!(on.best_move, off.best_move);
+        assert!(on.nodes < off.nodes);
+        // 子力很少、容易出现等着的残局，验证搜索后不会因为空着得出错误的结果
+        let fens = [
+            "4k4/4a4/4ba3/9/9/9/9/9/4R4/4K4 w - - 0 1",
+            "3k5/4a4/3a5/9/9/9/9/9/4N4/4K4 w - - 0 1",
+            "4k4/9/4b4/9/9/9/9/4N4/9/3K5 b - - 0 1",
+            "4ka3/4a4/9/9/2b6/9/9/3R5/9/5K3 w - - 0 1",
+        ];
+        for fen in fens {
+            let off = search(fen, 6, false);
+            let on = search(fen, 6, true);
+            assert_eq!((on.score, on.best_move), (off.score, off.best_move));
+        }
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "被将军时不能走空着")]
+    fn test_null_move_in_check() {
+        let mut board = Board::from_fen("4k4/9/9/9/9/9/9/9/4r4/3K5 w - - 0 1");
+        board.do_null_move();
+        board.undo_null_move();
+        // 被将军时走空着
+        let mut board = Board::from_fen("4k4/9/9/9/9/9/9/9/9/3Kr4 w - - 0 1");
+        board.do_null_move();
+    }
+
+    #[test]
+    fn test_null_move_history() {
+        let mut board = Board::init();
+        board.records = Arc::new(TranspositionTable::new(RECORD_SIZE));
+        board.do_null_move();
+        assert!(board
+            .check_history
+            .is_empty());
+        board.undo_null_move();
+        // 搜索时走过空着，之后的走棋历史和将军历史仍然一一对应
+        board.iterative_deepening(5);
+        assert_eq!(board.distance, 0);
+        assert!(board
+            .check_history
+            .is_empty());
+        // 双方来回走马，回到原来的局面，重复局面仍然能发现
+        board
+            .play_moves(&["h0g2", "h9g7", "g2h0", "g7h9"])
+            .unwrap();
+        assert_eq!(
+            board
+                .check_history
+                .len(),
+            board
+                .move_history
+                .len()
+        );
+        assert!(board.is_repetition());
+        assert_eq!(board.rep_status(), 1);
+        let result = board.iterative_deepening(5);
+        assert!(board.is_move_legal(
+            &result
+                .best_move
+                .unwrap()
+        ));
+        assert!(board.is_repetition());
+        assert_eq!(board.rep_status(), 1);
+    }
+
+    #[test]
+    fn test_search_after_repetition() {
+        // 实际对局走回了开局局面，根节点不算重复局面，照样给出着法
+        let mut board = Board::init();
+        board
+            .play_moves(&["b0c2", "b9c7", "c2b0", "c7b9"])
+            .unwrap();
+        assert!(board.is_repetition());
+        let m = board
+            .suggest_move(3)
+            .unwrap();
+        assert!(board.is_move_legal(&m));
+        let (v, bm) = board.alpha_beta_pvs(3, MIN, MAX);
+        assert!(bm.is_some());
+        assert_eq!(
+            v,
+            Board::init()
+                .alpha_beta_pvs(3, MIN, MAX)
+                .0
+        );
+        // 根节点以下走回出现过的局面仍然按和棋处理
+        let again = board
+            .chinese_to_move("马八进七")
+            .unwrap();
+        assert!(board.leads_to_repetition(&again));
+        assert_eq!(board.evaluate_move(&again, 2), 0);
+        assert!(board
+            .root_move_scores(2)
+            .contains(&(0, again)));
+    }
+
+    #[test]
+    fn test_staged_moves() {
+        let search = |fen: &str, staged: bool| {
+            let (result, sorted) = search_with(fen, 4, |c| c.staged_moves = staged);
+            ((result.score, result.best_move), sorted)
+        };
+        let fens = [
+            "r1bakabr1/9/1cn3nc1/p1p1p1p1p/9/9/P1P1P1P1P/1CN3NC1/9/R1BAKABR1 w - - 0 1",
+            "3ak4/4a4/9/p1p1p1p1p/1P1P1P1P1/9/9/9/9/4K4 w - - 0 1",
+            "3ak4/9/9/9/9/9/2n6/9/4C4/4K4 w - - 0 1",
+        ];
+        for fen in fens {
+            let (result, sorted) = search(fen, false);
+            let (staged_result, staged_sorted) = search(fen, true);
+            assert_eq!(staged_result, result);
+            // 吃子截断后不用再生成和排序其他着法
+            assert!(staged_sorted < sorted);
+        }
+    }
+
+    #[test]
+    fn test_counter_moves() {
+        let search = |fen: &str, counter_moves: bool| {
+            let (result, _) = search_with(fen, 7, |c| c.counter_moves = counter_moves);
+            (result.best_move, result.nodes)
+        };
+        let fen = "2bak4/4a4/2n1b4/p1p1p3p/6p2/2P6/P3P1P1P/4B1N2/4A4/2BAK1R2 w - - 0 1";
+        let (best_move, nodes) = search(fen, false);
+        let (counter_best_move, counter_nodes) = search(fen, true);
+        assert_eq!(counter_best_move, best_move);
+        assert!(counter_nodes < nodes);
+
+        // 应着只对记下它时的那一步生效
+        let mut board = Board::init();
+        board
+            .play_moves(&["h2e2", "h9g7"])
+            .unwrap();
+        let reply = board
+            .last_move()
+            .unwrap();
+        board.undo_move(&reply);
+        board.update_counter_move(&reply);
+        assert_eq!(board.counter_move(), Some(reply));
+        board.undo_last();
+        board
+            .play_moves(&["b2e2"])
+            .unwrap();
+        assert_eq!(board.counter_move(), None);
+    }
+
+    #[test]
+    fn test_legal_targets() {
+        let mut board = Board::init();
+        // 马腿被相挡住
+        let mut targets = board.legal_targets("b0".into());
+        targets.sort_by_key(|p| p.col);
+        assert_eq!(targets, vec!["a2".into(), "c2".into()]);
+        // 炮需要炮架才能吃子
+        let targets = board.legal_targets("b2".into());
+        assert!(targets.contains(&"b9".into()));
+        assert!(!targets.contains(&"b7".into()));
+        assert!(!targets.contains(&"i2".into()));
+        // 空位和对方棋子
+        assert!(board
+            .legal_targets("e5".into())
+            .is_empty());
+        assert!(board
+            .legal_targets("b9".into())
+            .is_empty());
+    }
+
+    #[test]
+    fn test_position_parse() {
+        assert_eq!("a0".parse::<Position>(), Ok(Position::new(9, 0)));
+        assert_eq!("i9".parse::<Position>(), Ok(Position::new(0, 8)));
+        assert_eq!("j0".parse::<Position>(), Err(PositionParseError::File('j')));
+        assert_eq!("a".parse::<Position>(), Err(PositionParseError::Length(1)));
+        assert_eq!("".parse::<Position>(), Err(PositionParseError::Length(0)));
+        assert_eq!("ax".parse::<Position>(), Err(PositionParseError::Rank('x')));
+    }
+
+    #[test]
+    fn test_move_display() {
+        let board = Board::init();
+        let m = Move {
+            player: Player::Red,
+            from: "b0".into(),
+            to: "c2".into(),
+            chess: board.chess_at("b0".into()),
+            capture: Chess::None,
+        };
+        assert_eq!(m.to_string(), "b0c2");
+        assert_eq!(format!("bestmove {}", m), "bestmove b0c2");
+    }
+
+    #[test]
+    fn test_flipped() {
+        let board = Board::from_fen(
+            "r1ba1a3/4kn3/2n1b4/pNp1p1p1p/4c4/6P2/P1P2R2P/1CcC5/9/2BAKAB2 w - - 0 1",
+        );
+        let flipped = board.flipped();
+        assert_eq!(flipped.turn, Player::Black);
+        // 红车变成对称位置上的黑车
+        assert_eq!(
+            board.chess_at(Position::new(6, 5)),
+            Chess::Red(ChessType::Rook)
+        );
+        assert_eq!(
+            flipped.chess_at(Position::new(3, 3)),
+            Chess::Black(ChessType::Rook)
+        );
+        assert_eq!(flipped.evaluate(flipped.turn), board.evaluate(board.turn));
+        assert_eq!(
+            flipped.zobrist_value,
+            ZOBRIST_TABLE.calc_chesses(&flipped.chesses)
+        );
+        // 翻转两次回到原来的局面
+        let twice = flipped.flipped();
+        assert_eq!(twice.chesses, board.chesses);
+        assert_eq!(twice.turn, board.turn);
+        assert_eq!(twice.zobrist_value, board.zobrist_value);
+    }
+
+    #[test]
+    fn test_is_move_legal() {
+        let make = |board: &Board, from: &str, to: &str| Move {
+            player: board.turn,
+            from: from.into(),
+            to: to.into(),
+            chess: board.chess_at(from.into()),
+            capture: board.chess_at(to.into()),
+        };
+        let mut board = Board::init();
+        assert!(board.is_move_legal(&make(&board, "h2", "e2")));
+        assert!(board.is_move_legal(&make(&board, "b0", "c2")));
+        // 马腿被相挡住，车被兵挡住，帅吃自己的士
+        assert!(!board.is_move_legal(&make(&board, "b0", "d1")));
+        assert!(!board.is_move_legal(&make(&board, "a0", "a5")));
+        assert!(!board.is_move_legal(&make(&board, "e0", "d0")));
+
+        // 车被牵制，离开这条线会让帅被将军
+        let mut board = Board::from_fen("4k4/9/9/9/9/9/9/4r4/4R4/4K4 w - - 0 1");
+        let chesses = board.chesses;
+        let zobrist = (board.zobrist_value, board.zobrist_value_lock);
+        assert!(!board.is_move_legal(&make(&board, "e1", "d1")));
+        assert!(board.is_move_legal(&make(&board, "e1", "e2")));
+        assert!(board.is_move_legal_cloned(&make(&board, "e0", "d0")));
+        // 棋盘和zobrist值都没有变
+        assert_eq!(board.chesses, chesses);
+        assert_eq!((board.zobrist_value, board.zobrist_value_lock), zobrist);
+        assert_eq!(board.turn, Player::Red);
+        assert!(board
+            .move_history
+            .is_empty());
+        assert_eq!(board.distance, 0);
+    }
+
+    #[test]
+    fn test_checking_moves() {
+        // 车可以平到九路或进到对方将旁边将军，帅和将对脸不合法
+        let mut board = Board::from_fen("4k4/R8/9/9/9/9/9/9/9/3K5 w - - 0 1");
+        let mut targets = board
+            .checking_moves()
+            .iter()
+            .map(|m| m.to)
+            .collect::<Vec<_>>();
+        targets.sort_by_key(|p| p.col);
+        assert_eq!(targets, vec!["a9".into(), "e8".into()]);
+
+        // 排序后将军的着法排在普通着法前面
+        let mut moves = board.generate_move(false);
+        board.sort_moves(&mut moves);
+        assert!(moves[..2]
+            .iter()
+            .all(|m| board.gives_check(m)));
+    }
+
+    #[test]
+    fn test_checks_directly() {
+        // 车、炮、马、兵直接将军，和真的走一步的结果一样，炮要隔着一个子才能将军
+        for fen in [
+            "4k4/R8/9/9/9/9/9/9/9/3K5 w - - 0 1",
+            "3k5/9/9/3n5/9/9/9/2C6/9/4K4 w - - 0 1",
+            "4k4/9/9/9/4N4/9/9/9/9/3K5 w - - 0 1",
+            "9/4k4/3P5/9/9/9/9/9/9/3K5 w - - 0 1",
+            "5k3/9/9/9/9/9/9/9/2p6/3K5 b - - 0 1",
+        ] {
+            let mut board = Board::from_fen(fen);
+            let moves = board
+                .generate_move(false)
+                .into_iter()
+                .filter(|m| board.is_move_legal_cloned(m))
+                .collect::<Vec<_>>();
+            let checks = moves
+                .iter()
+                .filter(|m| board.checks_directly(m))
+                .count();
+            assert!(checks > 0, "{}", fen);
+            for m in moves.iter() {
+                assert_eq!(
+                    board.checks_directly(m),
+                    board.gives_check(m),
+                    "{} {}",
+                    fen,
+                    m
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_classify_move() {
+        // 车吃卒、吃马将军、平到九路将军和普通着法
+        let mut board = Board::from_fen("4k4/R3n4/9/p8/9/9/9/9/9/3K5 w - - 0 1");
+        let moves = board.generate_move(false);
+        let find = |to: &str| {
+            let to: Position = to.into();
+            moves
+                .iter()
+                .find(|m| m.to == to)
+                .cloned()
+                .unwrap()
+        };
+        assert_eq!(board.classify_move(&find("a6")), MoveKind::Capture);
+        assert_eq!(board.classify_move(&find("e8")), MoveKind::CaptureCheck);
+        assert_eq!(board.classify_move(&find("a9")), MoveKind::Check);
+        assert_eq!(board.classify_move(&find("a7")), MoveKind::Quiet);
+        assert!(MoveKind::CaptureCheck.is_capture() && MoveKind::CaptureCheck.is_check());
+        assert!(!MoveKind::Quiet.is_capture() && !MoveKind::Quiet.is_check());
+    }
+
+    #[test]
+    fn test_quies_limits() {
+        let fen = "r2akr3/3na4/b3b2cn/2p1p1p1p/p6RP/9/P1P1PCP2/N3c1C2/9/2BAKABNR w - - 0 1";
+        let mut board = Board::from_fen(fen);
+        board.quies(MIN, MAX);
+        let nodes = board.nodes;
+
+        // 节点数用完后直接返回局面分
+        let mut board = Board::from_fen(fen);
+        board
+            .config
+            .node_limit = Some(nodes / 4);
+        let v = board.quies(MIN, MAX);
+        assert!(board.nodes <= nodes / 4 + 1);
+        assert!(board.is_stopped());
+        assert!(v > KILL && v < -KILL);
+
+        // 限制静态搜索的步数
+        let mut board = Board::from_fen(fen);
+        board
+            .config
+            .quies_max_ply = 2;
+        board.quies(MIN, MAX);
+        assert!(board.nodes < nodes);
+        let mut board = Board::from_fen(fen);
+        board
+            .config
+            .quies_max_ply = 0;
+        assert_eq!(board.quies(MIN, MAX), board.evaluate(board.turn));
+    }
+
+    #[test]
+    fn test_insufficient_material() {
+        let mut board = Board::from_fen("3k5/9/9/9/9/9/9/9/9/4K4 w - - 0 1");
+        assert!(board.is_insufficient_material());
+        assert_eq!(board.game_status(), GameStatus::Draw);
+        let mut board = Board::from_fen("3akab2/9/9/9/9/9/9/9/4A4/3AK4 w - - 0 1");
+        assert!(board.is_insufficient_material());
+        assert_eq!(board.game_status(), GameStatus::Draw);
+        let mut board = Board::from_fen("3k5/9/9/9/9/9/9/9/4P4/3AK4 w - - 0 1");
+        assert!(!board.is_insufficient_material());
+        assert_eq!(board.game_status(), GameStatus::Playing);
+        // 被将死
+        let mut board = Board::from_fen("3k5/3R5/3R5/9/9/9/9/9/9/4K4 b - - 0 1");
+        assert_eq!(board.game_status(), GameStatus::RedWin);
+
+        // 将吃掉最后一个兵后只剩双方的将帅，搜索给出和棋的分数
+        let mut board = Board::from_fen("3k5/3P5/9/9/9/9/9/9/9/4K4 b - - 0 1");
+        let (v, bm) = board.alpha_beta_pvs(2, MIN, MAX);
+        assert_eq!(v, 0);
+        assert_eq!(bm.unwrap().capture, Chess::Red(ChessType::Pawn));
+    }
+
+    #[test]
+    fn test_see() {
+        let capture = |board: &mut Board| {
+            board
+                .generate_move(true)
+                .into_iter()
+                .find(|m| m.from == "a0".into() && m.to == "a5".into())
+                .unwrap()
+        };
+        // 车吃没有保护的兵
+        let mut board = Board::from_fen("5k3/9/9/9/p8/9/9/9/9/R2K5 w - - 0 1");
+        let m = capture(&mut board);
+        assert_eq!(board.see(&m), 10);
+        // 车吃有车保护的兵，会被吃回
+        let mut board = Board::from_fen("r4k3/9/9/9/p8/9/9/9/9/R2K5 w - - 0 1");
+        let m = capture(&mut board);
+        assert_eq!(board.see(&m), 10 - 90);
+        // 有马保护，对方车吃回来会再被吃掉
+        let mut board = Board::from_fen("r4k3/9/9/9/p8/9/1N7/9/9/R2K5 w - - 0 1");
+        let m = capture(&mut board);
+        assert_eq!(board.see(&m), 10);
+    }
+
+    #[test]
+    fn test_transposition_table() {
+        let fen = "rnbakabnr/9/1c5c1/p1p1p1p1p/9/9/P1P1P1P1P/1C5C1/9/RNBAKABNR w - - 0 1";
+        let mut board = Board::from_fen(fen);
+        let (v, _) = board.alpha_beta_pvs(3, MIN, MAX);
+        let nodes = board.counter;
+
+        let mut board = Board::from_fen(fen);
+        board.records = Arc::new(TranspositionTable::new(1 << 16));
+        let (tt_v, tt_bm) = board.alpha_beta_pvs(3, MIN, MAX);
+        assert_eq!(tt_v, v);
+        assert!(tt_bm.is_some());
+        assert!(board.counter < nodes);
+    }
+
+    #[test]
+    fn test_parallel_search() {
+        let fen = "4k4/9/9/9/4r4/9/9/4R4/9/3K5 w - - 0 1";
+        let (v, _) = Board::from_fen(fen).alpha_beta_pvs(4, MIN, MAX);
+        let mut board = Board::from_fen(fen);
+        let (pv, bm) = board.parallel_search(2, 4, Instant::now() + Duration::from_secs(60));
+        assert_eq!(pv, v);
+        let bm = bm.unwrap();
+        assert!(board
+            .legal_targets(bm.from)
+            .contains(&bm.to));
+
+        // 时间到了要及时返回
+        let start = Instant::now();
+        Board::init().parallel_search(2, MAX_DEPTH, start + Duration::from_millis(200));
+        assert!(start.elapsed() < Duration::from_secs(10));
+        // 开始时已经到时间或者已经停止，也要等一个线程搜完一层，返回一个着法
+        let (_, bm) = Board::init().parallel_search(2, MAX_DEPTH, Instant::now());
+        assert!(bm.is_some());
+        let board = Board::init();
+        board
+            .stop
+            .store(true, Ordering::Relaxed);
+        let (_, bm) = board.parallel_search(2, MAX_DEPTH, start + Duration::from_secs(60));
+        assert!(bm.is_some());
+    }
+
+    #[test]
+    fn test_mate_distance_pruning() {
+        // 三步杀
+        let fen = "3ak4/9/9/9/9/9/9/9/9/R3K3R w - - 0 1";
+        let (result, _) = search_with(fen, 6, |c| c.mate_distance_pruning = false);
+        assert_eq!(result.score, MAX - 5);
+        let (pruned, _) = search_with(fen, 6, |c| c.mate_distance_pruning = true);
+        assert_eq!(pruned.score, MAX - 5);
+        assert!(pruned
+            .best_move
+            .is_some());
+        assert!(pruned.nodes < result.nodes);
+
+        // 搜索更深也报告最短的杀棋
+        let (v, bm) = Board::from_fen(fen).alpha_beta_pvs(7, MIN, MAX);
+        assert_eq!(v, MAX - 5);
+        assert!(bm.is_some());
+    }
+
+    #[test]
+    fn test_check_extension() {
+        // 三步杀，连续将军延伸后4层就能找到
+        let fen = "3ak4/9/9/9/9/9/9/9/9/R3K3R w - - 0 1";
+        let default = SearchConfig::default().check_extension;
+        let (result, _) = search_with(fen, 4, |c| c.check_extension = 0);
+        assert!(result.score < MAX - 5);
+        let (extended, _) = search_with(fen, 4, |c| c.check_extension = default);
+        assert_eq!(extended.score, MAX - 5);
+        assert!(extended
+            .best_move
+            .is_some());
+
+        // 双方都能反复将军，延伸有上限，搜索量不会爆炸
+        let fen = "2r1k4/9/9/9/9/9/9/9/4A4/3AK2R1 b - - 0 1";
+        let (result, _) = search_with(fen, 5, |c| c.check_extension = 0);
+        let (extended, _) = search_with(fen, 5, |c| c.check_extension = default);
+        assert!(extended.nodes < result.nodes * 4);
+        assert!(extended.score > KILL && extended.score < -KILL);
+        assert!(extended
+            .best_move
+            .is_some());
+    }
+
+    #[test]
+    fn test_fen_counters() {
+        let fen = "rnbakabnr/9/1c5c1/p1p1p1p1p/9/9/P1P1P1P1P/1C5C1/9/RNBAKABNR b - - 7 12";
+        let mut board = Board::from_fen(fen);
+        assert_eq!(board.halfmove_clock, 7);
+        assert_eq!(board.fullmove_number, 12);
+        assert_eq!(board.to_fen(), fen);
+
+        // 不吃子的步数继续累加，黑方走完回合数加一，吃子后重新计数
+        let m = board
+            .generate_move(false)
+            .into_iter()
+            .find(|m| m.capture == Chess::None)
+            .unwrap();
+        board.do_move(&m);
+        assert_eq!(board.no_capture_plies(), 8);
+        assert_eq!(board.fullmove(), 13);
+        let m = board
+            .generate_move(true)
+            .pop()
+            .unwrap();
+        board.do_move(&m);
+        assert_eq!(board.no_capture_plies(), 0);
+        assert!(board
+            .to_fen()
+            .ends_with(" b - - 0 13"));
+
+        // 没有计数字段时默认"0 1"
+        let board = Board::from_fen("4k4/9/9/9/9/9/9/9/9/4K4 w");
+        assert_eq!(board.to_fen(), "4k4/9/9/9/9/9/9/9/9/4K4 w - - 0 1");
     }
 
     #[test]
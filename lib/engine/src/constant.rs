@@ -4,7 +4,7 @@ use std::{collections::HashMap, sync::LazyLock};
 pub const MIN: i32 = -99999;
 pub const KILL: i32 = MIN + 100;
 pub const MAX: i32 = 99999;
-pub const RECORD_SIZE: i32 = 0x1FFFFE;
+pub const RECORD_SIZE: usize = 0x100000;
 pub const MAX_DEPTH: i32 = 64;
 
 pub static FEN_MAP: LazyLock<HashMap<char, Chess>> = LazyLock::new(|| {
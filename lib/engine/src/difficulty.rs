@@ -0,0 +1,36 @@
+use std::time::Duration;
+
+// 难度等级
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Difficulty {
+    Easy,
+    Medium,
+    Hard,
+}
+
+impl Difficulty {
+    // 最大搜索深度
+    pub fn max_depth(&self) -> i32 {
+        match self {
+            Difficulty::Easy => 2,
+            Difficulty::Medium => 4,
+            Difficulty::Hard => 6,
+        }
+    }
+    // 每步思考时间，界面上提示时会等着搜完，不能太长
+    pub fn movetime(&self) -> Duration {
+        match self {
+            Difficulty::Easy => Duration::from_millis(500),
+            Difficulty::Medium => Duration::from_secs(2),
+            Difficulty::Hard => Duration::from_secs(3),
+        }
+    }
+    // 是否在得分接近的着法中随机选择，(候选着法数, 分数差)
+    pub fn random_pick(&self) -> Option<(usize, i32)> {
+        match self {
+            Difficulty::Easy => Some((3, 50)),
+            Difficulty::Medium => None,
+            Difficulty::Hard => None,
+        }
+    }
+}
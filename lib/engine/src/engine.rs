@@ -1,20 +1,167 @@
-use crate::board::{Board, Move};
-use getrandom::getrandom;
+use crate::{
+    board::{
+        mate_distance, Board, GameStatus, Move, Player, Position, PositionParseError, SearchResult,
+    },
+    constant::{MAX, MAX_DEPTH, MIN},
+    notation::MoveFormat,
+    record::TranspositionTable,
+    zobrist::{next_seeded, rand64},
+};
 use regex::Regex;
-use std::io;
+use std::{
+    fs,
+    io::{self, BufRead},
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+// go命令没有指定深度时的搜索深度
+const DEFAULT_DEPTH: i32 = 6;
+// bench命令默认的搜索深度和测速用的局面
+const BENCH_DEPTH: i32 = 4;
+const BENCH_FENS: [&str; 4] = [
+    "rnbakabnr/9/1c5c1/p1p1p1p1p/9/9/P1P1P1P1P/1C5C1/9/RNBAKABNR w - - 0 1",
+    "r1bakab1r/9/1cn4cn/p1p1p1p1p/9/9/P1P1P1P1P/1C2C1N2/9/RNBAKAB1R w - - 0 1",
+    "r2akr3/3na4/b3b2cn/2p1p1p1p/p6RP/9/P1P1PCP2/N3c1C2/9/2BAKABNR w - - 0 1",
+    "3ak4/4a4/4b4/9/9/9/9/4B4/4A4/3K1R3 w - - 0 1",
+];
+// 多线程搜索没有时间限制，直到搜完指定深度或收到stop
+const PARALLEL_SEARCH_TIMEOUT: Duration = Duration::from_secs(24 * 60 * 60);
+// 开局库选着法的随机程度，0总是走权重最大的着法，100完全按权重随机
+const DEFAULT_BOOK_VARIETY: i32 = 100;
+// 搜索分数和和棋分数相差不超过这个值时，走回重复局面才算重复局面和棋
+const REPETITION_DRAW_BAND: i32 = 20;
+
+// FEN里走棋方后面的字段可能省略，也可能不是"- -"，整理成from_fen能识别的形式，
+// 最后两个字段都是数字时作为回合计数，否则用"0 1"
+fn normalize_fen(fen: &str) -> String {
+    let fields = fen
+        .split_whitespace()
+        .collect::<Vec<_>>();
+    let counters = match fields[2..] {
+        [.., halfmove, fullmove]
+            if halfmove
+                .parse::<i32>()
+                .is_ok()
+                && fullmove
+                    .parse::<i32>()
+                    .is_ok() =>
+        {
+            format!("{} {}", halfmove, fullmove)
+        }
+        _ => "0 1".to_owned(),
+    };
+    format!("{} {} - - {}", fields[0], fields[1], counters)
+}
+
+// 解析"h2e2"这样的着法字符串，返回(起点, 终点)
+pub fn parse_move_string(m: &str) -> Result<(Position, Position), PositionParseError> {
+    let (from, to) = if m.is_char_boundary(2) {
+        m.split_at(2)
+    } else {
+        (m, "")
+    };
+    Ok((from.parse()?, to.parse()?))
+}
 
 #[derive(Debug)]
 pub struct PreLoad {
     zobrist_value: u64,
     zobrist_value_check: u64,
+    // zobrist值不含走棋方，另外记下来，避免同样的摆法轮到另一方走时查到不能走的着法
+    turn: Player,
     best_move: String,
     weight: i32,
+    // 保存开局库时原样写回
+    fen: String,
+}
+
+impl PreLoad {
+    fn new(best_move: &str, weight: i32, fen: &str) -> Self {
+        let board = Board::from_fen(fen);
+        let (zobrist_value, zobrist_value_check) = board.zobrist_without_turn();
+        PreLoad {
+            zobrist_value,
+            zobrist_value_check,
+            turn: board.turn,
+            best_move: best_move.to_owned(),
+            weight,
+            fen: fen.to_owned(),
+        }
+    }
+}
+
+// 命令行参数，形如 "--depth 8 --hash 64 --threads 4"，没有给出的保持引擎的默认值
+#[derive(Debug, Default, PartialEq)]
+pub struct EngineArgs {
+    pub depth: Option<i32>,     // go命令没有指定深度时的搜索深度
+    pub hash: Option<usize>,    // 置换表占用的内存(MB)
+    pub threads: Option<usize>, // 搜索线程数
+}
+
+impl EngineArgs {
+    pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Self, String> {
+        let mut result = EngineArgs::default();
+        let mut args = args.into_iter();
+        while let Some(name) = args.next() {
+            let value = args
+                .next()
+                .ok_or(format!("参数{}缺少值", name))?;
+            let invalid = |_| format!("参数{}的值{}不合法", name, value);
+            match name.as_str() {
+                "--depth" => {
+                    result.depth = Some(
+                        value
+                            .parse()
+                            .map_err(invalid)?,
+                    )
+                }
+                "--hash" => {
+                    result.hash = Some(
+                        value
+                            .parse()
+                            .map_err(invalid)?,
+                    )
+                }
+                "--threads" => {
+                    result.threads = Some(
+                        value
+                            .parse()
+                            .map_err(invalid)?,
+                    )
+                }
+                _ => return Err(format!("不支持的参数{}", name)),
+            }
+        }
+        Ok(result)
+    }
 }
 
 // UCCI引擎
 pub struct UCCIEngine {
     pub board: Board,
     pub book: Vec<PreLoad>,
+    // 后台搜索线程，返回(分数, 最佳着法)
+    searching: Option<JoinHandle<(i32, Option<Move>)>>,
+    // 后台思考中，搜索完也要等到ponderhit或stop才输出着法
+    pondering: Arc<AtomicBool>,
+    // 输出bestmove用的记法，通过setoption name MoveFormat value chinese切换
+    move_format: MoveFormat,
+    // 开局库选着法的随机程度，通过setoption name BookVariety value 0..100设置
+    book_variety: i32,
+    // 是否使用开局库，通过setoption name UseBook value false关闭
+    use_book: bool,
+    // 开局库随机选着法用的随机数状态，with_seed固定种子后每次选的着法相同
+    rng_state: u64,
+    // go命令没有指定深度时的搜索深度
+    depth: i32,
+    // 搜索线程数，大于1时用多线程搜索
+    threads: usize,
 }
 
 impl UCCIEngine {
@@ -35,98 +182,210 @@ impl UCCIEngine {
                 let fen = tokens
                     .next()
                     .unwrap();
-                let board = Board::from_fen(fen);
-                book.push(PreLoad {
-                    zobrist_value: board.zobrist_value,
-                    zobrist_value_check: board.zobrist_value_lock,
-                    best_move: m.to_owned(),
-                    weight: weight
+                book.push(PreLoad::new(
+                    m,
+                    weight
                         .parse::<i32>()
                         .unwrap(),
-                });
+                    fen,
+                ));
             }
             book.sort_by(|a, b| {
                 a.zobrist_value
                     .cmp(&b.zobrist_value)
             });
             println!("加载开局库完成，共加载{}个局面", book.len());
-            println!("{:?}", book[1000]);
         }
         UCCIEngine {
             board: Board::init(),
             book,
+            searching: None,
+            pondering: Default::default(),
+            move_format: MoveFormat::Iccs,
+            book_variety: DEFAULT_BOOK_VARIETY,
+            use_book: true,
+            rng_state: rand64(),
+            depth: DEFAULT_DEPTH,
+            threads: 1,
+        }
+    }
+    // 固定随机种子，同样的种子从同一局面选出同样的开局库着法，方便复现对局
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.rng_state = seed;
+        self
+    }
+    // 用命令行参数设置默认的搜索深度、置换表大小和线程数
+    pub fn with_args(mut self, args: &EngineArgs) -> Self {
+        if let Some(depth) = args.depth {
+            self.depth = depth.clamp(1, MAX_DEPTH);
+        }
+        if let Some(hash) = args.hash {
+            self.board.records = Arc::new(TranspositionTable::with_megabytes(hash));
         }
+        if let Some(threads) = args.threads {
+            self.threads = threads.max(1);
+        }
+        self
+    }
+    fn next_random(&mut self) -> u64 {
+        next_seeded(&mut self.rng_state)
     }
-    pub fn search_in_book(&self) -> Option<String> {
-        let candidates = self
+    // 往开局库里加一个局面，重新排序保证search_in_book的二分查找有效
+    pub fn append_book_entry(&mut self, fen: &str, move_str: &str, weight: i32) {
+        self.book
+            .push(PreLoad::new(move_str, weight, fen));
+        self.book
+            .sort_by(|a, b| {
+                a.zobrist_value
+                    .cmp(&b.zobrist_value)
+            });
+    }
+    // 按加载时的格式保存开局库，每行为 "着法 权重 fen"
+    pub fn save_book<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let data = self
             .book
-            .binary_search_by(|probe| {
-                probe
-                    .zobrist_value
-                    .cmp(
-                        &self
-                            .board
-                            .zobrist_value,
-                    )
-            })
-            .map(|i| &self.book[i])
-            .into_iter()
-            .filter(|x| {
-                x.zobrist_value_check
-                    == self
-                        .board
-                        .zobrist_value_lock
-            })
-            .collect::<Vec<&PreLoad>>();
-        if candidates.len() > 0 {
-            let mut buf = [0; 4];
-            getrandom(&mut buf).unwrap();
-            let index = i32::from_be_bytes(buf) % candidates.len() as i32;
-            Some(
-                candidates[index as usize]
-                    .best_move
+            .iter()
+            .map(|x| format!("{} {} {}\n", x.best_move, x.weight, x.fen))
+            .collect::<String>();
+        fs::write(path, data)
+    }
+    // 开局库里当前局面的所有(着法, 权重)，按权重从大到小排列
+    pub fn book_candidates(&self) -> Vec<(String, i32)> {
+        self.book_candidates_of(&self.board)
+    }
+    fn book_candidates_of(&self, board: &Board) -> Vec<(String, i32)> {
+        let (zobrist_value, zobrist_value_check) = board.zobrist_without_turn();
+        let start = self
+            .book
+            .partition_point(|x| x.zobrist_value < zobrist_value);
+        let mut candidates = self.book[start..]
+            .iter()
+            .take_while(|x| x.zobrist_value == zobrist_value)
+            .filter(|x| x.zobrist_value_check == zobrist_value_check && x.turn == board.turn)
+            .map(|x| (x.best_move.clone(), x.weight))
+            .collect::<Vec<_>>();
+        candidates.sort_by_key(|(_, weight)| -weight);
+        candidates
+    }
+    // 从当前局面开始双方轮流走开局库里权重最大的着法，最多plies步，出了开局库就停止，
+    // 在克隆的棋盘上走，当前局面不变
+    pub fn book_line(&self, plies: usize) -> Vec<Move> {
+        let mut board = self
+            .board
+            .clone_position();
+        let mut line = vec![];
+        while line.len() < plies {
+            let Some((m_str, _)) = self
+                .book_candidates_of(&board)
+                .into_iter()
+                .next()
+            else {
+                break;
+            };
+            let Ok((from, to)) = parse_move_string(&m_str) else {
+                break;
+            };
+            let m = Move {
+                player: board.turn,
+                from,
+                to,
+                chess: board.chess_at(from),
+                capture: board.chess_at(to),
+            };
+            if !board.is_move_legal(&m) {
+                break;
+            }
+            board.do_move(&m);
+            line.push(m);
+        }
+        line
+    }
+    // 按book_variety的概率按权重随机选一个着法，否则走权重最大的着法，关闭开局库时返回None
+    pub fn search_in_book(&mut self) -> Option<String> {
+        if !self.use_book {
+            return None;
+        }
+        let candidates = self.book_candidates();
+        if candidates.is_empty() {
+            return None;
+        }
+        if self.next_random() % 100 >= self.book_variety as u64 {
+            return Some(
+                candidates[0]
+                    .0
                     .clone(),
-            )
-        } else {
-            None
+            );
+        }
+        // 权重不大于0的着法也保留一点被选中的机会
+        let total = candidates
+            .iter()
+            .map(|(_, weight)| (*weight).max(1) as u64)
+            .sum::<u64>();
+        let mut r = self.next_random() % total;
+        for (m, weight) in candidates.iter() {
+            let weight = (*weight).max(1) as u64;
+            if r < weight {
+                return Some(m.clone());
+            }
+            r -= weight;
         }
+        None
     }
 
-    pub fn start(&mut self) {
+    pub fn start(&mut self) -> io::Result<()> {
+        self.run(io::stdin().lock())
+    }
+    // 从input逐行读取命令，输入结束(EOF)或收到quit时退出
+    pub fn run<R: BufRead>(&mut self, mut input: R) -> io::Result<()> {
         loop {
             let mut cmd = String::new();
-            io::stdin()
-                .read_line(&mut cmd)
-                .unwrap();
-            cmd = cmd.replace("\n", "");
+            if input.read_line(&mut cmd)? == 0 {
+                self.stop();
+                break;
+            }
+            let cmd = cmd.trim();
             if cmd == "quit" {
+                self.stop();
                 break;
             }
             let mut token = cmd.splitn(2, " ");
-            let cmd = token
+            let name = token
                 .next()
-                .unwrap();
-            match cmd {
-                "ucci" => self.info(),
-                "isready" => self.is_ready(),
-                "position" => self.position(
-                    token
-                        .next()
-                        .unwrap(),
-                ),
-                "go" => {
-                    self.go(token
-                        .next()
-                        .unwrap()
-                        .split(" ")
-                        .last()
-                        .unwrap()
-                        .parse()
-                        .unwrap());
+                .unwrap_or("");
+            let args = token
+                .next()
+                .map(str::trim)
+                .filter(|args| !args.is_empty());
+            match (name, args) {
+                ("ucci", _) => self.info(),
+                ("isready", _) => self.is_ready(),
+                ("position", Some(args)) => {
+                    self.stop();
+                    self.position(args)
+                }
+                // 不带参数的go按默认深度搜索
+                ("go", args) => self.go_background(args.unwrap_or("")),
+                ("analyze", Some(args)) => {
+                    self.analyze(args);
+                }
+                ("setoption", Some(args)) => self.set_option(args),
+                ("position" | "setoption" | "analyze", None) => {
+                    println!("info string error {}命令缺少参数", name);
+                }
+                ("stop", _) => {
+                    self.stop();
+                }
+                ("ponderhit", _) => self.ponder_hit(),
+                ("bench", args) => {
+                    let depth = args
+                        .and_then(|d| d.parse().ok())
+                        .unwrap_or(BENCH_DEPTH);
+                    self.bench(depth);
                 }
                 _ => println!("not support"),
             }
         }
+        Ok(())
     }
 
     pub fn info(&self) {
@@ -135,6 +394,13 @@ impl UCCIEngine {
         println!("id author nealian");
         println!("id user 2021-2022 www.nealian.cn");
         println!("option usemillisec type check");
+        println!("option MoveFormat type combo var iccs var chinese default iccs");
+        println!(
+            "option BookVariety type spin min 0 max 100 default {}",
+            DEFAULT_BOOK_VARIETY
+        );
+        println!("option Contempt type spin min -1000 max 1000 default 0");
+        println!("option UseBook type check default true");
         println!("ucciok");
     }
 
@@ -142,60 +408,456 @@ impl UCCIEngine {
         println!("readyok");
     }
 
+    // 参数形如 "name MoveFormat value chinese"
+    pub fn set_option(&mut self, param: &str) {
+        let regex = Regex::new(r#"^name (?P<name>\S+)(?: value (?P<value>.*))?$"#).unwrap();
+        let Some(captures) = regex.captures(param) else {
+            println!("info string error setoption参数不合法: {}", param);
+            return;
+        };
+        let value = captures
+            .name("value")
+            .map(|v| v.as_str())
+            .unwrap_or("");
+        match &captures["name"] {
+            "MoveFormat" => match value {
+                "iccs" => self.move_format = MoveFormat::Iccs,
+                "chinese" => self.move_format = MoveFormat::Chinese,
+                _ => println!("info string error MoveFormat不支持{}", value),
+            },
+            "BookVariety" => match value.parse::<i32>() {
+                Ok(v) => self.book_variety = v.clamp(0, 100),
+                Err(_) => println!("info string error BookVariety不支持{}", value),
+            },
+            "Contempt" => match value.parse::<i32>() {
+                Ok(v) => {
+                    self.board
+                        .config
+                        .contempt = v
+                }
+                Err(_) => println!("info string error Contempt不支持{}", value),
+            },
+            "UseBook" => match value {
+                "true" => self.use_book = true,
+                "false" => self.use_book = false,
+                _ => println!("info string error UseBook不支持{}", value),
+            },
+            name => println!("info string error 不支持的选项{}", name),
+        }
+    }
+
+    // 坐标记法、WXF记法或中文记法的着法，坐标记法不合法时返回None并输出原因
+    fn parse_move(&mut self, m_str: &str) -> Option<Move> {
+        UCCIEngine::parse_move_on(&mut self.board, m_str)
+    }
+    // 在指定的棋盘上解析着法，先按WXF记法找合法着法，找不到时按坐标记法解析；
+    // 坐标记法只按棋盘上的棋子填好着法，不检查是否合法
+    fn parse_move_on(board: &mut Board, m_str: &str) -> Option<Move> {
+        if !m_str.is_ascii() {
+            return board.chinese_to_move(m_str);
+        }
+        if let Some(m) = board.wxf_to_move(m_str) {
+            return Some(m);
+        }
+        let (from, to) = match parse_move_string(m_str) {
+            Ok(positions) => positions,
+            Err(e) => {
+                println!("info string 着法{}不合法: {}", m_str, e);
+                return None;
+            }
+        };
+        Some(Move {
+            player: board.turn,
+            from,
+            to,
+            chess: board.chess_at(from),
+            capture: board.chess_at(to),
+        })
+    }
+
+    // 参数形如 "fen <FEN> moves <着法>..." 或 "startpos moves <着法>..."，
+    // FEN后面不用的字段和回合计数可以省略，不能识别时保持原来的局面并输出提示
     pub fn position(&mut self, param: &str) {
         let regex = Regex::new(
-            r#"^(?:fen (?P<fen>[kabnrcpKABNRCP1-9/]+ [wrb] - - \d+ \d+)|(?P<startpos>startpos))(?: moves (?P<moves>[a-i]\d[a-i]\d(?: [a-i]\d[a-i]\d)*))?$"#,
+            r#"^(?:fen\s+(?P<fen>[kabnrcpKABNRCP1-9/]+\s+[wrb](?:\s+\S+)*?)|(?P<startpos>startpos))(?:\s+moves(?P<moves>(?:\s+\S+)*))?\s*$"#,
         ).unwrap();
-        for captures in regex.captures_iter(param) {
-            if let Some(fen) = captures.name("fen") {
-                self.board = Board::from_fen(fen.as_str());
-            }
-            if let Some(_) = captures.name("startpos") {
-                self.board = Board::init();
-            }
-            if let Some(moves) = captures.name("moves") {
-                for m in moves
-                    .as_str()
-                    .split(" ")
-                {
-                    let (from, to) = m.split_at(2);
-                    self.board
-                        .apply_move(&Move {
-                            player: self.board.turn,
-                            from: from.into(),
-                            to: to.into(),
-                            chess: self
-                                .board
-                                .chess_at(from.into()),
-                            capture: self
-                                .board
-                                .chess_at(to.into()),
-                        });
-                }
+        let Some(captures) = regex.captures(param.trim()) else {
+            println!("info string unrecognized position {}", param);
+            return;
+        };
+        // 新局面沿用setoption设置的搜索参数和评价参数
+        let config = self
+            .board
+            .config
+            .clone();
+        let eval_params = self
+            .board
+            .eval_params
+            .clone();
+        let evaluator = self
+            .board
+            .evaluator
+            .clone();
+        let records = self
+            .board
+            .records
+            .clone();
+        if let Some(fen) = captures.name("fen") {
+            self.board = Board::from_fen(&normalize_fen(fen.as_str()));
+        }
+        if let Some(_) = captures.name("startpos") {
+            self.board = Board::init();
+        }
+        self.board.config = config;
+        self.board
+            .eval_params = eval_params;
+        self.board.evaluator = evaluator;
+        self.board.records = records;
+        if let Some(moves) = captures.name("moves") {
+            // 坐标记法和中文记法可以混用，后面的着法要在走过前面着法的局面上解析，
+            // 先在复制的棋盘上解析出所有着法，再一起走；有一步不合法时一步也不走，停在起始局面
+            let moves = moves
+                .as_str()
+                .split_whitespace()
+                .collect::<Vec<_>>();
+            let mut scratch = self
+                .board
+                .clone_position();
+            let mut parsed = vec![];
+            for m_str in moves.iter() {
+                let Some(m) = UCCIEngine::parse_move_on(&mut scratch, m_str)
+                    .filter(|m| scratch.is_move_legal(m))
+                else {
+                    break;
+                };
+                scratch.do_move(&m);
+                parsed.push(m);
+            }
+            // 对局中出现过的局面记在棋盘上，搜索时也能发现重复局面
+            let result = if parsed.len() < moves.len() {
+                Err(parsed.len())
+            } else {
+                self.board
+                    .apply_moves(&parsed)
+            };
+            if let Err(i) = result {
+                println!("info string 着法{}不合法，不执行任何着法", moves[i]);
             }
         }
+        // 搜索从当前局面开始计算距离
+        self.board.distance = 0;
     }
 
     pub fn go(&mut self, depth: i32) {
         if let Some(m) = self.search_in_book() {
-            println!("bestmove {}", m);
+            println!("bestmove {}", self.book_move_output(&m));
             return;
         }
-        let (value, best_move) = self
+        let SearchResult {
+            score: value,
+            best_move,
+            ..
+        } = self
             .board
             .iterative_deepening(depth);
+        let repetition = UCCIEngine::leads_to_repetition_draw(&self.board, &best_move, value);
+        UCCIEngine::print_best_move(&self.board, self.move_format, value, best_move, repetition);
+    }
+    // 走完这步后的局面在对局中出现过，循环里没有长将、长捉，搜索分数也是和棋分数，
+    // 才是走进了重复局面和棋；根节点的和棋分数按contempt减分
+    fn leads_to_repetition_draw(board: &Board, m: &Option<Move>, value: i32) -> bool {
+        let Some(m) = m
+            .as_ref()
+            .filter(|m| m.is_valid() && board.leads_to_repetition(m))
+        else {
+            return false;
+        };
+        let draw = -board
+            .config
+            .contempt;
+        if (value - draw).abs() > REPETITION_DRAW_BAND {
+            return false;
+        }
+        let mut board = board.clone_position();
+        board.do_move(m);
+        board.repetition_verdict() == Some(GameStatus::Draw)
+    }
+    // 开局库里的着法是坐标记法，中文记法下转换后再输出
+    fn book_move_output(&mut self, m_str: &str) -> String {
+        if self.move_format == MoveFormat::Chinese {
+            if let Some(m) = self.parse_move(m_str) {
+                return self
+                    .board
+                    .move_to_chinese(&m);
+            }
+        }
+        m_str.to_owned()
+    }
+    fn best_move_output(
+        board: &Board,
+        format: MoveFormat,
+        value: i32,
+        best_move: Option<Move>,
+        repetition: bool,
+    ) -> String {
         if let Some(m) = best_move {
             if m.is_valid() {
-                println!(
-                    "bestmove {}{} value {}",
-                    m.from.to_string(),
-                    m.to.to_string(),
-                    value
-                );
+                let m_str = match format {
+                    MoveFormat::Iccs => m.to_string(),
+                    MoveFormat::Chinese => board.move_to_chinese(&m),
+                };
+                let output = format!("bestmove {} value {}", m_str, value);
+                // 最佳着法会形成重复局面，告诉界面这盘棋是和棋
+                if repetition {
+                    return format!("info string draw by repetition\n{}", output);
+                }
+                return output;
+            }
+        }
+        "nobestmove".to_owned()
+    }
+    fn print_best_move(
+        board: &Board,
+        format: MoveFormat,
+        value: i32,
+        best_move: Option<Move>,
+        repetition: bool,
+    ) {
+        println!(
+            "{}",
+            UCCIEngine::best_move_output(board, format, value, best_move, repetition)
+        );
+    }
+    // 分析时每完成一层输出的信息，主要变例用坐标记法，
+    // 找到杀棋时加上"mate 回合数"，mate是mate_distance得到的步数，被杀时是负数
+    fn info_output(depth: i32, value: i32, mate: Option<i32>, pv: &[Move]) -> String {
+        let pv = pv
+            .iter()
+            .map(|m| m.to_string())
+            .collect::<Vec<_>>()
+            .join(" ");
+        match mate {
+            Some(plies) => format!(
+                "info depth {} score {} mate {} pv {}",
+                depth,
+                value,
+                plies.signum() * (plies.abs() + 1) / 2,
+                pv
+            ),
+            None => format!("info depth {} score {} pv {}", depth, value, pv),
+        }
+    }
+    // 在后台线程搜索，不阻塞命令的读取，参数形如 "ponder depth 8"，"movetime 1000"限制思考的毫秒数，
+    // "nobook"只在这一次搜索不查开局库，"nodes 100000"限制搜索的节点数，结果和机器快慢无关，
+    // "infinite"时不限深度地分析，每完成一层输出info，直到收到stop才输出着法
+    pub fn go_background(&mut self, param: &str) {
+        self.stop();
+        let mut ponder = false;
+        let mut infinite = false;
+        let mut nobook = false;
+        let mut depth = self.depth;
+        let mut movetime = None;
+        let mut nodes = None;
+        let mut tokens = param.split_whitespace();
+        while let Some(t) = tokens.next() {
+            match t {
+                "ponder" => ponder = true,
+                "infinite" => infinite = true,
+                "nobook" => nobook = true,
+                "depth" => {
+                    if let Some(d) = tokens
+                        .next()
+                        .and_then(|d| d.parse().ok())
+                    {
+                        depth = d;
+                    }
+                }
+                "nodes" => {
+                    nodes = tokens
+                        .next()
+                        .and_then(|t| {
+                            t.parse::<u64>()
+                                .ok()
+                        });
+                }
+                "movetime" => {
+                    movetime = tokens
+                        .next()
+                        .and_then(|t| t.parse().ok())
+                        .map(Duration::from_millis);
+                }
+                _ => {}
+            }
+        }
+        if !ponder && !infinite && !nobook {
+            if let Some(m) = self.search_in_book() {
+                println!("bestmove {}", self.book_move_output(&m));
                 return;
             }
         }
-        println!("nobestmove");
+        self.pondering
+            .store(ponder, Ordering::Relaxed);
+        // 克隆的棋盘和引擎共享停止标志
+        let mut board = self.board.clone();
+        board.deadline = movetime.map(|t| Instant::now() + t);
+        if let Some(n) = nodes {
+            board
+                .config
+                .node_limit = Some(n);
+        }
+        let pondering = self
+            .pondering
+            .clone();
+        let format = self.move_format;
+        let threads = self.threads;
+        self.searching = Some(thread::spawn(move || {
+            let (value, best_move) = if infinite {
+                board.analyze(|depth, value, pv| {
+                    let mate = mate_distance(value);
+                    println!("{}", UCCIEngine::info_output(depth, value, mate, pv));
+                })
+            } else if threads > 1 {
+                board.parallel_search(threads, depth, Instant::now() + PARALLEL_SEARCH_TIMEOUT)
+            } else {
+                let SearchResult {
+                    score: value,
+                    best_move,
+                    ..
+                } = board.iterative_deepening(depth);
+                (value, best_move)
+            };
+            // 后台思考要等到ponderhit，无限分析提前结束（比如已经找到杀棋）也要等到stop
+            while (infinite || pondering.load(Ordering::Relaxed)) && !board.is_stopped() {
+                thread::sleep(Duration::from_millis(1));
+            }
+            let repetition = UCCIEngine::leads_to_repetition_draw(&board, &best_move, value);
+            UCCIEngine::print_best_move(&board, format, value, best_move.clone(), repetition);
+            (value, best_move)
+        }));
+    }
+    // 停止后台搜索，输出目前为止找到的最佳着法
+    pub fn stop(&mut self) -> Option<(i32, Option<Move>)> {
+        let handle = self
+            .searching
+            .take()?;
+        self.board
+            .stop
+            .store(true, Ordering::Relaxed);
+        let result = handle
+            .join()
+            .unwrap();
+        self.board
+            .stop
+            .store(false, Ordering::Relaxed);
+        self.pondering
+            .store(false, Ordering::Relaxed);
+        Some(result)
+    }
+    // 对手走了猜测的着法，后台思考转为正常搜索
+    pub fn ponder_hit(&mut self) {
+        self.pondering
+            .store(false, Ordering::Relaxed);
+    }
+    // 测速，固定的几个局面搜索到固定深度，返回总节点数和用时
+    pub fn bench(&mut self, depth: i32) -> (u64, Duration) {
+        let start = Instant::now();
+        let mut nodes = 0;
+        for fen in BENCH_FENS {
+            let mut board = Board::from_fen(fen);
+            board.alpha_beta_pvs(depth, MIN, MAX);
+            nodes += board.nodes;
+        }
+        let elapsed = start.elapsed();
+        let nps = nodes as f64
+            / elapsed
+                .as_secs_f64()
+                .max(0.001);
+        println!(
+            "info string bench depth {} nodes {} time {} nps {:.0}",
+            depth,
+            nodes,
+            elapsed.as_millis(),
+            nps
+        );
+        (nodes, elapsed)
+    }
+    // 不用界面直接分析一个局面，参数是FEN，后面可以跟搜索深度，如"analyze 3ak4/9/... w - - 0 1 8"，
+    // FEN的字段数是偶数，参数有奇数个字段时最后一个是深度；不改变当前局面
+    pub fn analyze(&mut self, param: &str) -> Option<SearchResult> {
+        let fields = param
+            .split_whitespace()
+            .collect::<Vec<_>>();
+        let (fen, depth) = match fields.split_last() {
+            Some((depth, fen)) if fields.len() % 2 == 1 => (fen.join(" "), depth.parse().ok()),
+            _ => (fields.join(" "), Some(self.depth)),
+        };
+        let Some(depth) = depth else {
+            println!("info string error 分析深度不是数字 {}", param);
+            return None;
+        };
+        let mut board = match Board::try_from_fen(&fen) {
+            Ok(board) => board,
+            Err(e) => {
+                println!("info string error {}", e);
+                return None;
+            }
+        };
+        board.config = self
+            .board
+            .config
+            .clone();
+        board.eval_params = self
+            .board
+            .eval_params
+            .clone();
+        board.evaluator = self
+            .board
+            .evaluator
+            .clone();
+        board.records = self
+            .board
+            .records
+            .clone();
+        let result = board.iterative_deepening(depth);
+        println!("{}", UCCIEngine::analyze_output(&board, &result));
+        Some(result)
+    }
+    // 分析结果，最佳着法和主要变例同时给出坐标记法和WXF记法
+    fn analyze_output(board: &Board, result: &SearchResult) -> String {
+        let Some(best_move) = result
+            .best_move
+            .as_ref()
+            .filter(|m| m.is_valid())
+        else {
+            return "nobestmove".to_owned();
+        };
+        // 主要变例要边走边转换成WXF记法
+        let mut line = board.clone_position();
+        let wxf = result
+            .pv
+            .iter()
+            .map(|m| {
+                let wxf = line.move_to_wxf(m);
+                line.do_move(m);
+                wxf
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+        let pv = result
+            .pv
+            .iter()
+            .map(|m| m.to_string())
+            .collect::<Vec<_>>()
+            .join(" ");
+        format!(
+            "depth {}\nscore {}\nbestmove {} ({})\npv {}\npv wxf {}",
+            result.depth_reached,
+            result.score,
+            best_move,
+            board.move_to_wxf(best_move),
+            pv,
+            wxf
+        )
     }
     pub fn quit() {
         println!("bye");
@@ -204,7 +866,13 @@ impl UCCIEngine {
 
 #[cfg(test)]
 mod tests {
-    use crate::engine::UCCIEngine;
+    use crate::{
+        board::{Board, Move, Player, Position},
+        constant::{MAX, MIN},
+        engine::{parse_move_string, EngineArgs, PreLoad, UCCIEngine, REPETITION_DRAW_BAND},
+        notation::MoveFormat,
+    };
+    use std::{thread, time::Duration};
 
     #[test]
     fn test_ucci_engine() {
@@ -226,6 +894,734 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_parse_move_string() {
+        assert_eq!(parse_move_string("h2e2"), Ok(("h2".into(), "e2".into())));
+        assert!(parse_move_string("h2e").is_err());
+        assert!(parse_move_string("z2e2").is_err());
+        assert!(parse_move_string("中国").is_err());
+
+        // 有一步不合法时所有着法都不执行，停在起始局面
+        let mut engine = UCCIEngine::new(None);
+        engine.position("startpos");
+        let zobrist_value = engine
+            .board
+            .zobrist_value;
+        engine.position("startpos moves h2e2 x0a1 h9g7");
+        assert_eq!(engine.board.turn, Player::Red);
+        // 走法不合法的着法同样一步都不执行
+        engine.position("startpos moves h2e2 a9a0 h9g7");
+        assert_eq!(
+            engine
+                .board
+                .zobrist_value,
+            zobrist_value
+        );
+        assert_eq!(engine.board.turn, Player::Red);
+        assert!(engine
+            .board
+            .move_history
+            .is_empty());
+    }
+
+    #[test]
+    fn test_draw_by_repetition() {
+        let mut engine = UCCIEngine::new(None);
+        engine.position("startpos moves h0g2 h9g7 g2h0 g7h9 h0g2 h9g7 g2h0");
+        let (from, to) = parse_move_string("g7h9").unwrap();
+        let m = Move {
+            player: engine.board.turn,
+            from,
+            to,
+            chess: engine
+                .board
+                .chess_at(from),
+            capture: engine
+                .board
+                .chess_at(to),
+        };
+        // 搜索这步得到的是和棋分数
+        let value = engine
+            .board
+            .evaluate_move(&m, 3);
+        assert!(UCCIEngine::leads_to_repetition_draw(
+            &engine.board,
+            &Some(m.clone()),
+            value
+        ));
+        assert_eq!(
+            UCCIEngine::best_move_output(
+                &engine.board,
+                MoveFormat::Iccs,
+                value,
+                Some(m.clone()),
+                true
+            ),
+            format!(
+                "info string draw by repetition\nbestmove g7h9 value {}",
+                value
+            )
+        );
+        // 分数不是和棋分数时不算
+        assert!(!UCCIEngine::leads_to_repetition_draw(
+            &engine.board,
+            &Some(m),
+            value + 100
+        ));
+        // 红车长捉没有保护的黑炮，黑炮躲回原处时红方判负，不是和棋
+        engine.position("fen 4k4/9/c8/8R/9/9/9/9/9/3K5 w - - 0 1 moves i6i7 a7a6 i7i6");
+        let (from, to) = parse_move_string("a6a7").unwrap();
+        let m = Move {
+            player: engine.board.turn,
+            from,
+            to,
+            chess: engine
+                .board
+                .chess_at(from),
+            capture: engine
+                .board
+                .chess_at(to),
+        };
+        let value = engine
+            .board
+            .evaluate_move(&m, 3);
+        // 搜索把重复局面都当作和棋，要靠循环里的长捉判断
+        assert!(value.abs() <= REPETITION_DRAW_BAND);
+        assert!(engine
+            .board
+            .leads_to_repetition(&m));
+        assert!(!UCCIEngine::leads_to_repetition_draw(
+            &engine.board,
+            &Some(m),
+            value
+        ));
+
+        // 重复局面只记在棋盘上，重新设置局面后从头记录
+        engine.position("startpos moves h0g2 h9g7 g2h0 g7h9");
+        let init = Board::init();
+        assert_eq!(
+            engine
+                .board
+                .zobrist_value,
+            init.zobrist_value
+        );
+        assert_eq!(
+            engine
+                .board
+                .zobrist_value_lock,
+            init.zobrist_value_lock
+        );
+        assert_eq!(
+            engine
+                .board
+                .distance,
+            0
+        );
+        assert!(engine
+            .board
+            .is_repetition());
+        assert_eq!(
+            engine
+                .board
+                .rep_status(),
+            1
+        );
+        engine.position("startpos moves h0g2 h9g7 g2h0");
+        // 没有走回原来局面的着法不算重复
+        let (from, to) = parse_move_string("b9c7").unwrap();
+        let m = Move {
+            player: engine.board.turn,
+            from,
+            to,
+            chess: engine
+                .board
+                .chess_at(from),
+            capture: engine
+                .board
+                .chess_at(to),
+        };
+        let value = engine
+            .board
+            .evaluate_move(&m, 3);
+        assert!(!UCCIEngine::leads_to_repetition_draw(
+            &engine.board,
+            &Some(m),
+            value
+        ));
+    }
+
+    #[test]
+    fn test_run_malformed_commands() {
+        let mut engine = UCCIEngine::new(None);
+        // 缺少参数的命令不会让引擎崩溃，输入结束后正常退出
+        let input = "go\nposition\n\nunknown\nisready\n";
+        assert!(engine
+            .run(input.as_bytes())
+            .is_ok());
+        assert!(engine
+            .searching
+            .is_none());
+        assert!(engine
+            .run("position startpos moves h2e2\nquit\nisready\n".as_bytes())
+            .is_ok());
+        assert_eq!(engine.board.turn, Player::Black);
+    }
+
+    #[test]
+    fn test_stop_and_ponder_hit() {
+        let mut engine = UCCIEngine::new(None);
+        engine.position("fen 3ak4/9/9/9/9/9/9/9/9/R3K3R w - - 0 1");
+        engine.go_background("depth 30");
+        thread::sleep(Duration::from_millis(200));
+        let (_, best_move) = engine
+            .stop()
+            .unwrap();
+        assert!(best_move.is_some());
+        assert!(engine
+            .stop()
+            .is_none());
+
+        // 后台思考搜索完成后，等到ponderhit才输出着法
+        engine.go_background("ponder depth 1");
+        thread::sleep(Duration::from_millis(200));
+        assert!(!engine
+            .searching
+            .as_ref()
+            .unwrap()
+            .is_finished());
+        engine.ponder_hit();
+        thread::sleep(Duration::from_millis(200));
+        assert!(engine
+            .searching
+            .as_ref()
+            .unwrap()
+            .is_finished());
+        assert!(engine
+            .stop()
+            .unwrap()
+            .1
+            .is_some());
+    }
+
+    #[test]
+    fn test_engine_args() {
+        let parse = |s: &str| {
+            EngineArgs::parse(
+                s.split_whitespace()
+                    .map(String::from),
+            )
+        };
+        assert_eq!(parse("").unwrap(), EngineArgs::default());
+        let args = parse("--depth 3 --hash 16 --threads 2").unwrap();
+        assert_eq!(
+            args,
+            EngineArgs {
+                depth: Some(3),
+                hash: Some(16),
+                threads: Some(2),
+            }
+        );
+        assert!(parse("--depth").is_err());
+        assert!(parse("--hash many").is_err());
+        assert!(parse("--ponder true").is_err());
+
+        let mut engine = UCCIEngine::new(None).with_args(&args);
+        assert_eq!(engine.depth, 3);
+        assert_eq!(engine.threads, 2);
+        assert!(!engine
+            .board
+            .records
+            .is_empty());
+        // 换局面后置换表保留
+        engine.position("startpos moves h2e2");
+        assert!(!engine
+            .board
+            .records
+            .is_empty());
+        engine.go_background("");
+        thread::sleep(Duration::from_millis(500));
+        let (_, best_move) = engine
+            .stop()
+            .unwrap();
+        assert!(best_move.is_some());
+
+        // 不带参数的go命令搜索到命令行设置的深度；输入结束时引擎会停止搜索，
+        // 读完命令后等一会儿再结束输入，让搜索先完成
+        struct Wait;
+        impl std::io::Read for Wait {
+            fn read(&mut self, _: &mut [u8]) -> std::io::Result<usize> {
+                thread::sleep(Duration::from_millis(500));
+                Ok(0)
+            }
+        }
+        for depth in [1, 3] {
+            let args = EngineArgs {
+                depth: Some(depth),
+                hash: Some(1),
+                ..Default::default()
+            };
+            let mut engine = UCCIEngine::new(None).with_args(&args);
+            let input = "position fen 3ak4/9/9/9/9/9/2n6/9/4C4/4K4 w - - 0 1\ngo\n".as_bytes();
+            assert!(engine
+                .run(std::io::BufReader::new(std::io::Read::chain(input, Wait)))
+                .is_ok());
+            assert_eq!(
+                engine
+                    .board
+                    .find_record()
+                    .unwrap()
+                    .depth,
+                depth
+            );
+        }
+    }
+
+    #[test]
+    fn test_position_variants() {
+        let mut engine = UCCIEngine::new(None);
+        // 省略不用的字段和回合计数，多余的空格
+        engine.position("fen  4k4/9/9/9/9/9/9/9/4R4/3K5 b   moves e9f9  ");
+        assert_eq!(
+            engine
+                .board
+                .to_fen(),
+            "5k3/9/9/9/9/9/9/9/4R4/3K5 w - - 1 2"
+        );
+        // 不用的字段不是"- -"
+        engine.position("fen 4k4/9/9/9/9/9/9/9/4R4/3K5 w KQkq e3 3 12");
+        assert_eq!(
+            engine
+                .board
+                .to_fen(),
+            "4k4/9/9/9/9/9/9/9/4R4/3K5 w - - 3 12"
+        );
+        engine.position("startpos moves");
+        assert_eq!(
+            engine
+                .board
+                .to_fen(),
+            Board::init().to_fen()
+        );
+
+        // 不能识别的参数保持原来的局面
+        engine.position("startpos moves h2e2");
+        let fen = engine
+            .board
+            .to_fen();
+        for param in ["fen 4k4/9/9 x - - 0 1", "fen", "startpos h2e2", "initpos"] {
+            engine.position(param);
+            assert_eq!(
+                engine
+                    .board
+                    .to_fen(),
+                fen
+            );
+        }
+    }
+
+    #[test]
+    fn test_go_nobook() {
+        let mut engine = UCCIEngine::new(include_str!("../BOOK.DAT").into());
+        engine.position("startpos");
+        // 开局库里有的局面直接输出着法，不开搜索线程
+        engine.go_background("depth 1");
+        assert!(engine
+            .searching
+            .is_none());
+        engine.go_background("nobook depth 1");
+        thread::sleep(Duration::from_millis(500));
+        let (_, best_move) = engine
+            .stop()
+            .unwrap();
+        assert!(best_move.is_some());
+        // 只对这一次搜索有效
+        assert!(engine.use_book);
+        engine.go_background("depth 1");
+        assert!(engine
+            .searching
+            .is_none());
+    }
+
+    #[test]
+    fn test_go_nodes() {
+        let mut engine = UCCIEngine::new(None);
+        engine.position("fen 3ak4/9/9/9/9/9/2n6/9/4C4/4K4 w - - 0 1");
+        let mut search = || {
+            engine.go_background("nodes 20000 depth 64");
+            while !engine
+                .searching
+                .as_ref()
+                .unwrap()
+                .is_finished()
+            {
+                thread::sleep(Duration::from_millis(10));
+            }
+            engine
+                .stop()
+                .unwrap()
+        };
+        // 同样的节点数得到同样的结果
+        let result = search();
+        assert!(result.1.is_some());
+        assert_eq!(search(), result);
+        // 引擎自己的搜索参数不受影响
+        assert_eq!(
+            engine
+                .board
+                .config
+                .node_limit,
+            None
+        );
+    }
+
+    #[test]
+    fn test_go_movetime() {
+        let mut engine = UCCIEngine::new(None);
+        engine.position("fen 3ak4/9/9/9/9/9/2n6/9/4C4/4K4 w - - 0 1");
+        engine.go_background("movetime 100 depth 64");
+        thread::sleep(Duration::from_millis(2000));
+        // 时间到了自己停下，不用等stop
+        assert!(engine
+            .searching
+            .as_ref()
+            .unwrap()
+            .is_finished());
+        assert!(engine
+            .stop()
+            .unwrap()
+            .1
+            .is_some());
+    }
+
+    #[test]
+    fn test_go_infinite() {
+        let mut engine = UCCIEngine::new(None);
+        engine.position("fen 3ak4/9/9/9/9/9/9/9/9/R3K3R w - - 0 1");
+        engine.go_background("infinite");
+        // 没有收到stop就一直分析下去
+        thread::sleep(Duration::from_millis(300));
+        assert!(!engine
+            .searching
+            .as_ref()
+            .unwrap()
+            .is_finished());
+        let (_, best_move) = engine
+            .stop()
+            .unwrap();
+        assert!(best_move.is_some());
+
+        // 很快就分析完的局面也要等到stop
+        engine.position("fen 3k5/9/9/9/9/9/9/9/9/3RK4 b - - 0 1");
+        engine.go_background("infinite");
+        thread::sleep(Duration::from_millis(300));
+        assert!(!engine
+            .searching
+            .as_ref()
+            .unwrap()
+            .is_finished());
+        assert!(engine
+            .stop()
+            .is_some());
+        let pv = [
+            Move {
+                from: Position::from("h2"),
+                to: Position::from("e2"),
+                ..Move::stay()
+            },
+            Move {
+                from: Position::from("h9"),
+                to: Position::from("g7"),
+                ..Move::stay()
+            },
+        ];
+        assert_eq!(
+            UCCIEngine::info_output(2, 15, None, &pv),
+            "info depth 2 score 15 pv h2e2 h9g7"
+        );
+        // 三步（两个回合）杀
+        assert_eq!(
+            UCCIEngine::info_output(3, MAX - 3, Some(3), &pv),
+            format!("info depth 3 score {} mate 2 pv h2e2 h9g7", MAX - 3)
+        );
+        assert_eq!(
+            UCCIEngine::info_output(2, MIN + 2, Some(-2), &pv),
+            format!("info depth 2 score {} mate -1 pv h2e2 h9g7", MIN + 2)
+        );
+    }
+
+    #[test]
+    fn test_analyze() {
+        let mut engine = UCCIEngine::new(None);
+        let fen = "4k4/9/9/9/4r4/9/9/9/4R4/3K5 w - - 0 1";
+        // 红车可以白吃黑车
+        let result = engine
+            .analyze(&format!("{} 4", fen))
+            .unwrap();
+        let best_move = result
+            .best_move
+            .clone()
+            .unwrap();
+        let mut board = Board::from_fen(fen);
+        assert!(board.is_move_legal(&best_move));
+        assert_eq!(best_move.to_string(), "e1e5");
+        let output = UCCIEngine::analyze_output(&board, &result);
+        assert!(output.contains("bestmove e1e5 (R5+4)"));
+        assert!(output.contains("pv wxf R5+4"));
+        // 当前局面不变
+        assert_eq!(
+            engine
+                .board
+                .to_fen(),
+            Board::init().to_fen()
+        );
+        // 没有深度时用默认深度，只有局面和走棋方的FEN也可以
+        assert!(engine
+            .analyze("4k4/9/9/9/4r4/9/9/9/4R4/3K5 w")
+            .is_some());
+        assert!(engine
+            .analyze("4k4/9/9 w 4")
+            .is_none());
+        assert!(engine
+            .analyze(&format!("{} deep", fen))
+            .is_none());
+    }
+
+    #[test]
+    fn test_mixed_move_format() {
+        let mut engine = UCCIEngine::new(None);
+        // 坐标记法和中文记法混用
+        engine.position("startpos moves h2e2 马8进7 b0c2 b9c7");
+        let mut expected = UCCIEngine::new(None);
+        expected.position("startpos moves h2e2 h9g7 b0c2 b9c7");
+        assert_eq!(engine.board.turn, Player::Red);
+        assert_eq!(
+            engine
+                .board
+                .to_fen(),
+            expected
+                .board
+                .to_fen()
+        );
+        // 中文记法的着法不合法时，所有着法都不执行
+        engine.position("startpos moves 炮二平五 马8进6 车一进一");
+        assert_eq!(engine.board.turn, Player::Red);
+        // 坐标记法、WXF记法和中文记法混用
+        engine.position("startpos moves C2.5 h9g7 马二进三 R9.8 b0c2");
+        expected.position("startpos moves h2e2 h9g7 h0g2 i9h9 b0c2");
+        assert_eq!(
+            engine
+                .board
+                .to_fen(),
+            expected
+                .board
+                .to_fen()
+        );
+        assert_eq!(engine.board.turn, Player::Black);
+
+        assert!(engine
+            .run("setoption name MoveFormat value chinese\n".as_bytes())
+            .is_ok());
+        assert_eq!(engine.move_format, MoveFormat::Chinese);
+        engine.position("startpos moves h2e2");
+        let m = engine
+            .board
+            .chinese_to_move("马8进7")
+            .unwrap();
+        assert_eq!(
+            UCCIEngine::best_move_output(&engine.board, engine.move_format, 10, Some(m), false),
+            "bestmove 马8进7 value 10"
+        );
+        engine.set_option("name MoveFormat value iccs");
+        assert_eq!(engine.move_format, MoveFormat::Iccs);
+    }
+
+    #[test]
+    fn test_contempt_option() {
+        let mut engine = UCCIEngine::new(None);
+        engine.set_option("name Contempt value 30");
+        // 重新设置局面后仍然有效
+        engine.position("startpos moves h2e2");
+        assert_eq!(
+            engine
+                .board
+                .config
+                .contempt,
+            30
+        );
+        engine.set_option("name Contempt value abc");
+        assert_eq!(
+            engine
+                .board
+                .config
+                .contempt,
+            30
+        );
+    }
+
+    #[test]
+    fn test_book_keys() {
+        let data = include_str!("../BOOK.DAT")
+            .lines()
+            .step_by(200)
+            .collect::<Vec<_>>()
+            .join("\n");
+        let mut engine = UCCIEngine::new(Some(&data));
+        for line in data.lines() {
+            let mut tokens = line.splitn(3, " ");
+            let m = tokens
+                .next()
+                .unwrap();
+            tokens.next();
+            let fen = tokens
+                .next()
+                .unwrap();
+            // 开局库和position命令得到的局面用同一种方法计算zobrist值
+            let preload = PreLoad::new(m, 1, fen);
+            let board = Board::from_fen(fen);
+            assert_eq!(
+                (preload.zobrist_value, preload.zobrist_value_check),
+                board.zobrist_without_turn()
+            );
+            engine.position(&format!("fen {}", fen));
+            assert!(engine
+                .book_candidates()
+                .iter()
+                .any(|(x, _)| x == m));
+        }
+
+        // 同样的摆法轮到另一方走时查不到
+        engine
+            .position("fen rnbakabnr/9/1c5c1/p1p1p1p1p/9/9/P1P1P1P1P/1C5C1/9/RNBAKABNR b - - 0 1");
+        assert!(engine
+            .book_candidates()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_save_book() {
+        let fen = "rnbakabnr/9/1c5c1/p1p1p1p1p/9/9/P1P1P1P1P/1C2C4/9/RNBAKABNR b - - 0 1";
+        let mut engine = UCCIEngine::new(Some(
+            "h2e2 10 rnbakabnr/9/1c5c1/p1p1p1p1p/9/9/P1P1P1P1P/1C5C1/9/RNBAKABNR w - - 0 1\n",
+        ));
+        engine.append_book_entry(fen, "h9g7", 5);
+        let path = std::env::temp_dir().join(format!("book_{}.dat", std::process::id()));
+        engine
+            .save_book(&path)
+            .unwrap();
+        let data = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(data.lines().count(), 2);
+
+        let mut engine = UCCIEngine::new(Some(&data));
+        engine.position(&format!("fen {}", fen));
+        assert_eq!(engine.search_in_book(), Some("h9g7".to_owned()));
+        engine.position("startpos");
+        assert_eq!(engine.search_in_book(), Some("h2e2".to_owned()));
+    }
+
+    #[test]
+    fn test_book_line() {
+        let mut engine = UCCIEngine::new(Some(include_str!("../BOOK.DAT")));
+        let line = engine.book_line(6);
+        assert!(!line.is_empty());
+        assert_eq!(line[0].to_string(), engine.book_candidates()[0].0);
+        // 双方轮流走，每一步都合法
+        let mut board = Board::init();
+        for (i, m) in line
+            .iter()
+            .enumerate()
+        {
+            let player = if i % 2 == 0 {
+                Player::Red
+            } else {
+                Player::Black
+            };
+            assert_eq!(m.player, player);
+            assert!(board.is_move_legal(m));
+            board.do_move(m);
+        }
+        // 当前局面不变
+        assert_eq!(
+            engine
+                .board
+                .to_fen(),
+            Board::init().to_fen()
+        );
+        // 开局库里没有的局面
+        engine.position("fen 4k4/9/9/9/9/9/9/9/4R4/3K5 w - - 0 1");
+        assert!(engine
+            .book_line(6)
+            .is_empty());
+    }
+
+    #[test]
+    fn test_book_weighted_selection() {
+        let fen = "rnbakabnr/9/1c5c1/p1p1p1p1p/9/9/P1P1P1P1P/1C5C1/9/RNBAKABNR w - - 0 1";
+        let mut engine = UCCIEngine::new(None);
+        engine.append_book_entry(fen, "b2e2", 10);
+        engine.append_book_entry(fen, "h2e2", 90);
+        engine.append_book_entry(
+            "rnbakabnr/9/1c5c1/p1p1p1p1p/9/9/P1P1P1P1P/1C2C4/9/RNBAKABNR b - - 0 1",
+            "h9g7",
+            50,
+        );
+        assert_eq!(
+            engine.book_candidates(),
+            vec![("h2e2".to_owned(), 90), ("b2e2".to_owned(), 10)]
+        );
+
+        // 按权重随机，90:10的着法大约九成选中h2e2
+        let draws = 2000;
+        let count = (0..draws)
+            .filter(|_| engine.search_in_book() == Some("h2e2".to_owned()))
+            .count();
+        assert!(count > draws * 8 / 10 && count < draws * 97 / 100);
+
+        // 随机程度为0时总是走权重最大的着法
+        engine.set_option("name BookVariety value 0");
+        assert!((0..100).all(|_| engine.search_in_book() == Some("h2e2".to_owned())));
+
+        // 关闭开局库后不再查库，重新打开后恢复
+        engine.set_option("name UseBook value false");
+        assert_eq!(engine.search_in_book(), None);
+        engine.set_option("name UseBook value maybe");
+        assert_eq!(engine.search_in_book(), None);
+        engine.set_option("name UseBook value true");
+        assert_eq!(engine.search_in_book(), Some("h2e2".to_owned()));
+    }
+
+    #[test]
+    fn test_book_seed() {
+        let fen = "rnbakabnr/9/1c5c1/p1p1p1p1p/9/9/P1P1P1P1P/1C5C1/9/RNBAKABNR w - - 0 1";
+        let picks = |seed: u64| {
+            let mut engine = UCCIEngine::new(None).with_seed(seed);
+            for m in ["b2e2", "h2e2", "b0c2", "g3g4"] {
+                engine.append_book_entry(fen, m, 10);
+            }
+            engine.set_option("name BookVariety value 100");
+            (0..20)
+                .map(|_| {
+                    engine
+                        .search_in_book()
+                        .unwrap()
+                })
+                .collect::<Vec<_>>()
+        };
+        // 种子相同时选出的着法序列相同
+        assert_eq!(picks(7), picks(7));
+        assert_ne!(picks(7), picks(8));
+    }
+
+    #[test]
+    fn test_bench() {
+        let mut engine = UCCIEngine::new(None);
+        let (nodes, _) = engine.bench(2);
+        assert!(nodes > 0);
+        assert!(engine
+            .run("bench 1\n".as_bytes())
+            .is_ok());
+    }
+
     #[test]
     fn test_kill() {
         let mut engine = UCCIEngine::new(None);
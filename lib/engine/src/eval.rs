@@ -0,0 +1,503 @@
+use std::sync::Arc;
+
+use crate::board::{Board, ChessType, Player, BOARD_HEIGHT, BOARD_WIDTH, TOTAL_PHASE};
+
+// 位置分表，红方视角，黑方棋子翻转后查表
+pub type ValueTable = [[i32; BOARD_WIDTH as usize]; BOARD_HEIGHT as usize];
+
+const KING_VALUE_TABLE: ValueTable = [
+    [0, 0, 0, 0, 0, 0, 0, 0, 0],
+    [0, 0, 0, 0, 0, 0, 0, 0, 0],
+    [0, 0, 0, 0, 0, 0, 0, 0, 0],
+    [0, 0, 0, 0, 0, 0, 0, 0, 0],
+    [0, 0, 0, 0, 0, 0, 0, 0, 0],
+    [0, 0, 0, 0, 0, 0, 0, 0, 0],
+    [0, 0, 0, 0, 0, 0, 0, 0, 0],
+    [0, 0, 0, 1, 1, 1, 0, 0, 0],
+    [0, 0, 0, 2, 2, 2, 0, 0, 0],
+    [0, 0, 0, 11, 15, 11, 0, 0, 0],
+];
+
+const ADVISOR_VALUE_TABLE: ValueTable = [
+    [0, 0, 0, 0, 0, 0, 0, 0, 0],
+    [0, 0, 0, 0, 0, 0, 0, 0, 0],
+    [0, 0, 0, 0, 0, 0, 0, 0, 0],
+    [0, 0, 0, 0, 0, 0, 0, 0, 0],
+    [0, 0, 0, 0, 0, 0, 0, 0, 0],
+    [0, 0, 0, 0, 0, 0, 0, 0, 0],
+    [0, 0, 0, 0, 0, 0, 0, 0, 0],
+    [0, 0, 0, 20, 0, 20, 0, 0, 0],
+    [0, 0, 0, 0, 23, 0, 0, 0, 0],
+    [0, 0, 0, 20, 0, 20, 0, 0, 0],
+];
+
+const BISHOP_VALUE_TABLE: ValueTable = [
+    [0, 0, 0, 0, 0, 0, 0, 0, 0],
+    [0, 0, 0, 0, 0, 0, 0, 0, 0],
+    [0, 0, 0, 0, 0, 0, 0, 0, 0],
+    [0, 0, 0, 0, 0, 0, 0, 0, 0],
+    [0, 0, 0, 0, 0, 0, 0, 0, 0],
+    [0, 0, 20, 0, 0, 0, 20, 0, 0],
+    [0, 0, 0, 0, 0, 0, 0, 0, 0],
+    [18, 0, 0, 0, 23, 0, 0, 0, 18],
+    [0, 0, 0, 0, 0, 0, 0, 0, 0],
+    [0, 0, 20, 0, 0, 0, 20, 0, 0],
+];
+
+const ROOK_VALUE_TABLE: ValueTable = [
+    [206, 208, 207, 213, 214, 213, 207, 208, 206],
+    [206, 212, 209, 216, 233, 216, 209, 212, 206],
+    [206, 208, 207, 214, 216, 214, 207, 208, 206],
+    [206, 213, 213, 216, 216, 216, 213, 213, 206],
+    [208, 211, 211, 214, 215, 214, 211, 211, 208],
+    [208, 212, 212, 214, 215, 214, 212, 212, 208],
+    [204, 209, 204, 212, 214, 212, 204, 209, 204],
+    [198, 208, 204, 212, 212, 212, 204, 208, 198],
+    [200, 208, 206, 212, 200, 212, 206, 208, 200],
+    [194, 206, 204, 212, 200, 212, 204, 206, 194],
+];
+
+const KNIGHT_VALUE_TABLE: ValueTable = [
+    [90, 90, 90, 96, 90, 96, 90, 90, 90],
+    [90, 96, 103, 97, 94, 97, 103, 96, 90],
+    [92, 98, 99, 103, 99, 103, 99, 98, 92],
+    [93, 108, 100, 107, 100, 107, 100, 108, 93],
+    [90, 100, 99, 103, 104, 103, 99, 100, 90],
+    [90, 98, 101, 102, 103, 102, 101, 98, 90],
+    [92, 94, 98, 95, 98, 95, 98, 94, 92],
+    [93, 92, 94, 95, 92, 95, 94, 92, 93],
+    [85, 90, 92, 93, 78, 93, 92, 90, 85],
+    [88, 85, 90, 88, 90, 88, 90, 85, 88],
+];
+
+const CANNON_VALUE_TABLE: ValueTable = [
+    [100, 100, 96, 91, 90, 91, 96, 100, 100],
+    [98, 98, 96, 92, 89, 92, 96, 98, 98],
+    [97, 97, 96, 91, 92, 91, 96, 97, 97],
+    [96, 99, 99, 98, 100, 98, 99, 99, 96],
+    [96, 96, 96, 96, 100, 96, 96, 96, 96],
+    [95, 96, 99, 96, 100, 96, 99, 96, 95],
+    [96, 96, 96, 96, 96, 96, 96, 96, 96],
+    [97, 96, 100, 99, 101, 99, 100, 96, 97],
+    [96, 97, 98, 98, 98, 98, 98, 97, 96],
+    [96, 96, 97, 99, 99, 99, 97, 96, 96],
+];
+
+const PAWN_VALUE_TABLE: ValueTable = [
+    [9, 9, 9, 11, 13, 11, 9, 9, 9],
+    [19, 24, 34, 42, 44, 42, 34, 24, 19],
+    [19, 24, 32, 37, 37, 37, 32, 24, 19],
+    [19, 23, 27, 29, 30, 29, 27, 23, 19],
+    [14, 18, 20, 27, 29, 27, 20, 18, 14],
+    [7, 0, 13, 0, 16, 0, 13, 0, 7],
+    [7, 0, 7, 0, 15, 0, 7, 0, 7],
+    [0, 0, 0, 0, 0, 0, 0, 0, 0],
+    [0, 0, 0, 0, 0, 0, 0, 0, 0],
+    [0, 0, 0, 0, 0, 0, 0, 0, 0],
+];
+
+// 残局时的帅、兵位置分，帅要到九宫中间助攻，兵越靠近对方九宫越有价值
+const KING_END_VALUE_TABLE: ValueTable = [
+    [0, 0, 0, 0, 0, 0, 0, 0, 0],
+    [0, 0, 0, 0, 0, 0, 0, 0, 0],
+    [0, 0, 0, 0, 0, 0, 0, 0, 0],
+    [0, 0, 0, 0, 0, 0, 0, 0, 0],
+    [0, 0, 0, 0, 0, 0, 0, 0, 0],
+    [0, 0, 0, 0, 0, 0, 0, 0, 0],
+    [0, 0, 0, 0, 0, 0, 0, 0, 0],
+    [0, 0, 0, 10, 15, 10, 0, 0, 0],
+    [0, 0, 0, 12, 20, 12, 0, 0, 0],
+    [0, 0, 0, 5, 8, 5, 0, 0, 0],
+];
+
+const PAWN_END_VALUE_TABLE: ValueTable = [
+    [10, 10, 12, 15, 18, 15, 12, 10, 10],
+    [30, 35, 45, 55, 60, 55, 45, 35, 30],
+    [30, 35, 45, 52, 55, 52, 45, 35, 30],
+    [28, 33, 40, 45, 48, 45, 40, 33, 28],
+    [22, 26, 30, 36, 40, 36, 30, 26, 22],
+    [10, 0, 16, 0, 20, 0, 16, 0, 10],
+    [10, 0, 10, 0, 18, 0, 10, 0, 10],
+    [0, 0, 0, 0, 0, 0, 0, 0, 0],
+    [0, 0, 0, 0, 0, 0, 0, 0, 0],
+    [0, 0, 0, 0, 0, 0, 0, 0, 0],
+];
+
+// 子力价值，不算位置，帅不计，按帅、仕、相、马、车、炮、兵的顺序
+const MATERIAL_VALUES: [i32; 7] = [0, 20, 20, 40, 90, 45, 10];
+
+// 轮到走棋的一方的先手加分
+const INITIATIVE_BONUS: i32 = 3;
+
+// 一方只剩帅仕相、另一方有车的残局，对局阶段不超过MOP_UP_PHASE时，
+// 对方的将离九宫中心每远一步加MOP_UP_EDGE_BONUS，车离对方的将每近一步加MOP_UP_ROOK_BONUS
+const MOP_UP_PHASE: i32 = TOTAL_PHASE / 4;
+const MOP_UP_EDGE_BONUS: i32 = 8;
+const MOP_UP_ROOK_BONUS: i32 = 1;
+
+// 评价用到的参数，默认值就是内置的位置分表，可以从toml文件加载调整后的参数，
+// 文件里没有写的项使用默认值
+#[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct EvalParams {
+    pub king: ValueTable,
+    pub advisor: ValueTable,
+    pub bishop: ValueTable,
+    pub knight: ValueTable,
+    pub rook: ValueTable,
+    pub cannon: ValueTable,
+    pub pawn: ValueTable,
+    pub king_end: ValueTable, // 残局时的帅
+    pub pawn_end: ValueTable, // 残局时的兵
+    pub material: [i32; 7],
+    pub initiative: i32, // 先手加分，设为0时不考虑先手
+}
+
+impl Default for EvalParams {
+    fn default() -> Self {
+        EvalParams {
+            king: KING_VALUE_TABLE,
+            advisor: ADVISOR_VALUE_TABLE,
+            bishop: BISHOP_VALUE_TABLE,
+            knight: KNIGHT_VALUE_TABLE,
+            rook: ROOK_VALUE_TABLE,
+            cannon: CANNON_VALUE_TABLE,
+            pawn: PAWN_VALUE_TABLE,
+            king_end: KING_END_VALUE_TABLE,
+            pawn_end: PAWN_END_VALUE_TABLE,
+            material: MATERIAL_VALUES,
+            initiative: INITIATIVE_BONUS,
+        }
+    }
+}
+
+impl EvalParams {
+    #[cfg(feature = "serde")]
+    pub fn from_toml_str(s: &str) -> Result<Self, toml::de::Error> {
+        toml::from_str(s)
+    }
+    #[cfg(feature = "serde")]
+    pub fn load<P: AsRef<std::path::Path>>(path: P) -> std::io::Result<Self> {
+        let s = std::fs::read_to_string(path)?;
+        EvalParams::from_toml_str(&s)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+    }
+    pub fn material_value(&self, ct: ChessType) -> i32 {
+        self.material[match ct {
+            ChessType::King => 0,
+            ChessType::Advisor => 1,
+            ChessType::Bishop => 2,
+            ChessType::Knight => 3,
+            ChessType::Rook => 4,
+            ChessType::Cannon => 5,
+            ChessType::Pawn => 6,
+        }]
+    }
+    // 估算对局阶段的权重，只计算车马炮的子力
+    pub fn phase_weight(&self, ct: ChessType) -> i32 {
+        match ct {
+            ChessType::Rook | ChessType::Knight | ChessType::Cannon => self.material_value(ct),
+            _ => 0,
+        }
+    }
+    // 开局表和残局表的位置分，row和col是红方视角的位置
+    pub fn position_value(&self, ct: ChessType, row: usize, col: usize) -> (i32, i32) {
+        let table = match ct {
+            ChessType::King => return (self.king[row][col], self.king_end[row][col]),
+            ChessType::Pawn => return (self.pawn[row][col], self.pawn_end[row][col]),
+            ChessType::Advisor => &self.advisor,
+            ChessType::Bishop => &self.bishop,
+            ChessType::Knight => &self.knight,
+            ChessType::Rook => &self.rook,
+            ChessType::Cannon => &self.cannon,
+        };
+        (table[row][col], table[row][col])
+    }
+}
+
+// 局面评价，返回player视角的分数，搜索只通过这个接口评价局面，
+// 实现这个trait就可以换上自己的评价函数，不用改搜索
+pub trait Evaluator: Send + Sync {
+    fn evaluate(&self, board: &Board, player: Player) -> i32;
+}
+
+// 内置评价的各项分数，都是player视角（己方减对方），各项相加就是DefaultEvaluator的评价；
+// 位置分表里已经含有子力价值，piece_square是表里的分数减去子力价值后剩下的部分
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct EvalBreakdown {
+    pub material: i32,     // 子力价值
+    pub piece_square: i32, // 位置分，按对局阶段在开局表和残局表之间过渡
+    pub mop_up: i32,       // 车对孤将时把将赶到边上的加分
+    pub initiative: i32,   // 先手加分
+}
+
+impl EvalBreakdown {
+    pub fn total(&self) -> i32 {
+        self.material + self.piece_square + self.mop_up + self.initiative
+    }
+}
+
+// 内置的评价：按对局阶段在开局表和残局表之间过渡的子力位置分，加上先手分，参数来自board.eval_params
+pub struct DefaultEvaluator;
+
+impl Evaluator for DefaultEvaluator {
+    fn evaluate(&self, board: &Board, player: Player) -> i32 {
+        eval_breakdown(board, player).total()
+    }
+}
+
+// 按DefaultEvaluator的算法分项计算，不管棋盘上设置的是哪个评价函数
+pub fn eval_breakdown(board: &Board, player: Player) -> EvalBreakdown {
+    let phase = board.phase();
+    let taper =
+        |opening: i32, end: i32| (opening * phase + end * (TOTAL_PHASE - phase)) / TOTAL_PHASE;
+    let mut red_score = 0;
+    let mut black_score = 0;
+    // 双方是否还有车马炮兵这样能进攻的子
+    let mut red_attacking = false;
+    let mut black_attacking = false;
+    for (pos, chess) in board.pieces() {
+        let Some(ct) = chess.chess_type() else {
+            continue;
+        };
+        if !matches!(ct, ChessType::King | ChessType::Advisor | ChessType::Bishop) {
+            if chess.belong_to(Player::Black) {
+                black_attacking = true
+            } else {
+                red_attacking = true
+            }
+        }
+        let pos = if chess.belong_to(Player::Black) {
+            pos.flip()
+        } else {
+            pos
+        };
+        let (opening, end) = board
+            .eval_params
+            .position_value(ct, pos.row as usize, pos.col as usize);
+        let score = taper(opening, end);
+        if chess.belong_to(Player::Black) {
+            black_score += score
+        } else {
+            red_score += score
+        }
+    }
+    let mut mop_up_score = 0;
+    if phase <= MOP_UP_PHASE {
+        match (red_attacking, black_attacking) {
+            (true, false) => mop_up_score = mop_up(board, Player::Red),
+            (false, true) => mop_up_score = -mop_up(board, Player::Black),
+            _ => {}
+        }
+    }
+    let sign = if player == Player::Red { 1 } else { -1 };
+    let material = board.material(player) - board.material(player.next());
+    EvalBreakdown {
+        material,
+        piece_square: sign * (red_score - black_score) - material,
+        mop_up: sign * mop_up_score,
+        initiative: board
+            .eval_params
+            .initiative,
+    }
+}
+
+// 车对孤将的残局只靠位置分常常找不到进展，来回走到和棋，
+// 加分引导把对方的将赶到九宫边上，车靠近对方的将；attacker没有车时不加分
+fn mop_up(board: &Board, attacker: Player) -> i32 {
+    let defender = attacker.next();
+    let Some(king) = board.king_position(defender) else {
+        return 0;
+    };
+    let Some(distance) = board
+        .pieces()
+        .filter(|(_, chess)| {
+            chess.belong_to(attacker) && chess.chess_type() == Some(ChessType::Rook)
+        })
+        .map(|(pos, _)| (pos.row - king.row).abs() + (pos.col - king.col).abs())
+        .min()
+    else {
+        return 0;
+    };
+    let center_row = if defender == Player::Black {
+        1
+    } else {
+        BOARD_HEIGHT - 2
+    };
+    let edge = (king.row - center_row).abs() + (king.col - BOARD_WIDTH / 2).abs();
+    MOP_UP_EDGE_BONUS * edge + MOP_UP_ROOK_BONUS * (BOARD_HEIGHT + BOARD_WIDTH - 2 - distance)
+}
+
+pub fn default_evaluator() -> Arc<dyn Evaluator> {
+    Arc::new(DefaultEvaluator)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::{
+        board::{Board, Player},
+        constant::{MAX, MIN},
+        eval::{mop_up, EvalBreakdown, EvalParams, Evaluator},
+    };
+
+    // 只算子力的评价
+    struct MaterialEvaluator;
+
+    impl Evaluator for MaterialEvaluator {
+        fn evaluate(&self, board: &Board, player: Player) -> i32 {
+            board.material(player) - board.material(player.next())
+        }
+    }
+
+    #[test]
+    fn test_custom_evaluator() {
+        let search = |board: &mut Board| {
+            board
+                .alpha_beta_pvs(3, MIN, MAX)
+                .1
+                .unwrap()
+        };
+        let mut board = Board::init();
+        let best_move = search(&mut board);
+        board.evaluator = Arc::new(MaterialEvaluator);
+        assert_eq!(board.evaluate(Player::Red), 0);
+        let material_best_move = search(&mut board);
+        assert_ne!(material_best_move, best_move);
+        assert!(board.is_move_legal(&material_best_move));
+        // 克隆出的棋盘用同一个评价函数
+        assert_eq!(
+            board
+                .clone()
+                .evaluate(Player::Black),
+            0
+        );
+    }
+
+    #[test]
+    fn test_default_params() {
+        let params = EvalParams::default();
+        let board = Board::init();
+        assert_eq!(*board.eval_params, params);
+        assert_eq!(
+            board.material(Player::Red),
+            2 * (20 + 20 + 40 + 90 + 45) + 5 * 10
+        );
+        assert_eq!(board.phase(), 48);
+    }
+
+    #[test]
+    fn test_mop_up() {
+        // 车帅对孤将，将越靠九宫的边角，红方的分数越高
+        let score = |fen: &str| Board::from_fen(fen).evaluate(Player::Red);
+        let center = score("9/4k4/9/9/9/9/9/9/4R4/3K5 w - - 0 1");
+        let edge = score("4k4/9/9/9/9/9/9/9/4R4/3K5 w - - 0 1");
+        let corner = score("5k3/9/9/9/9/9/9/9/4R4/3K5 w - - 0 1");
+        assert!(center < edge);
+        assert!(edge < corner);
+        assert_eq!(
+            mop_up(
+                &Board::from_fen("5k3/9/9/9/9/9/9/9/4R4/3K5 w - - 0 1"),
+                Player::Red
+            ),
+            16 + 8
+        );
+        // 车靠近对方的将也加分
+        let bonus = |fen: &str| mop_up(&Board::from_fen(fen), Player::Red);
+        assert!(
+            bonus("9/4k4/9/9/9/3R5/9/9/9/3K5 w - - 0 1")
+                > bonus("9/4k4/9/9/9/9/9/9/4R4/3K5 w - - 0 1")
+        );
+        // 对方还有卒时不加分，将走到角上只差位置分
+        let with_pawn = |fen: &str| score(&fen.replace("/9/9/4R4", "/4p4/9/4R4"));
+        assert!(
+            with_pawn("5k3/9/9/9/9/9/9/9/4R4/3K5 w - - 0 1")
+                - with_pawn("9/4k4/9/9/9/9/9/9/4R4/3K5 w - - 0 1")
+                < corner - center
+        );
+    }
+
+    #[test]
+    fn test_initiative() {
+        // 对称的局面只有先手加分
+        let mut board = Board::init();
+        assert_eq!(board.evaluate(Player::Red), 3);
+        assert_eq!(board.evaluate(Player::Black), 3);
+        board.eval_params = Arc::new(EvalParams {
+            initiative: 0,
+            ..Default::default()
+        });
+        assert_eq!(board.evaluate(Player::Red), 0);
+        board.eval_params = Arc::new(EvalParams {
+            initiative: 10,
+            ..Default::default()
+        });
+        assert_eq!(board.evaluate(Player::Black), 10);
+    }
+
+    #[test]
+    fn test_eval_breakdown() {
+        for fen in [
+            "rnbakabnr/9/1c5c1/p1p1p1p1p/9/9/P1P1P1P1P/1C5C1/9/RNBAKABNR w - - 0 1",
+            "r1bakab1r/9/1cn3nc1/p1p1p1p1p/9/2P6/P3P1P1P/1C2C1N2/9/RNBAKAB1R b - - 0 1",
+            "5k3/9/9/9/9/9/9/9/4R4/3K5 w - - 0 1",
+            "3k5/4R4/9/9/9/9/9/9/9/4K4 b - - 0 1",
+        ] {
+            let board = Board::from_fen(fen);
+            for player in [Player::Red, Player::Black] {
+                let breakdown = board.eval_breakdown(player);
+                assert_eq!(breakdown.total(), board.evaluate(player));
+            }
+        }
+        // 车对孤将：红方多一个车，将在角上还有赶将的加分
+        let board = Board::from_fen("5k3/9/9/9/9/9/9/9/4R4/3K5 w - - 0 1");
+        let breakdown = board.eval_breakdown(Player::Red);
+        assert_eq!(breakdown.material, 90);
+        assert_eq!(breakdown.mop_up, 16 + 8);
+        assert_eq!(breakdown.initiative, 3);
+        let black = board.eval_breakdown(Player::Black);
+        assert_eq!(black.material, -90);
+        assert_eq!(black.mop_up, -breakdown.mop_up);
+        // 对称的开局局面只有先手加分
+        assert_eq!(
+            Board::init().eval_breakdown(Player::Black),
+            EvalBreakdown {
+                initiative: 3,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_load_params() {
+        let params = EvalParams::default();
+        let s = toml::to_string(&params).unwrap();
+        assert_eq!(EvalParams::from_toml_str(&s).unwrap(), params);
+
+        // 只改车的位置分，其他参数沿用默认值
+        let rook = vec![format!("[{}]", ["300"; 9].join(", ")); 10].join(", ");
+        let params = EvalParams::from_toml_str(&format!("rook = [{}]", rook)).unwrap();
+        assert_eq!(params.rook[0][0], 300);
+        assert_eq!(params.knight, EvalParams::default().knight);
+
+        let mut board = Board::from_fen("4k4/9/9/9/9/9/9/9/9/R3K4 w - - 0 1");
+        let before = board.evaluate(Player::Red);
+        board.eval_params = Arc::new(params);
+        assert_eq!(
+            board.evaluate(Player::Red),
+            before + 300 - EvalParams::default().rook[9][0]
+        );
+
+        let path = std::env::temp_dir().join(format!("eval_{}.toml", std::process::id()));
+        std::fs::write(&path, "material = [0, 20, 20, 40, 100, 45, 10]").unwrap();
+        let params = EvalParams::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(params.material[4], 100);
+        std::fs::write(&path, "material = 1").unwrap();
+        assert!(EvalParams::load(&path).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,7 +1,11 @@
 #![feature(lazy_cell)]
 pub mod board;
 pub mod constant;
+pub mod difficulty;
 pub mod engine;
+pub mod eval;
+pub mod notation;
+pub mod record;
 pub mod zobrist;
 
 pub fn aaa() {}
@@ -0,0 +1,496 @@
+use crate::{
+    board::{Board, Chess, ChessType, Move, Player},
+    engine::parse_move_string,
+};
+
+// 着法的记法
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum MoveFormat {
+    Iccs,    // 坐标记法，如 h2e2
+    Chinese, // 中文纵线记法，如 炮二平五
+}
+
+const RED_NUMBERS: [char; 9] = ['一', '二', '三', '四', '五', '六', '七', '八', '九'];
+const BLACK_NUMBERS: [char; 9] = ['1', '2', '3', '4', '5', '6', '7', '8', '9'];
+
+fn chess_name(chess: Chess) -> char {
+    match chess {
+        Chess::Red(ChessType::King) => '帅',
+        Chess::Red(ChessType::Advisor) => '仕',
+        Chess::Red(ChessType::Bishop) => '相',
+        Chess::Red(ChessType::Pawn) => '兵',
+        Chess::Black(ChessType::King) => '将',
+        Chess::Black(ChessType::Advisor) => '士',
+        Chess::Black(ChessType::Bishop) => '象',
+        Chess::Black(ChessType::Pawn) => '卒',
+        Chess::Red(ct) | Chess::Black(ct) => match ct {
+            ChessType::Knight => '马',
+            ChessType::Rook => '车',
+            _ => '炮',
+        },
+        Chess::None => ' ',
+    }
+}
+
+// 红方用汉字从右往左数纵线，黑方用数字从自己的右边（棋盘左边）数
+fn number_name(player: Player, n: i32) -> char {
+    match player {
+        Player::Red => RED_NUMBERS[(n - 1) as usize],
+        Player::Black => BLACK_NUMBERS[(n - 1) as usize],
+    }
+}
+
+fn file_number(player: Player, col: i32) -> i32 {
+    match player {
+        Player::Red => 9 - col,
+        Player::Black => col + 1,
+    }
+}
+
+// 把各种写法统一成move_to_chinese输出的写法，兼容繁体、全角数字和红黑混用的棋子名
+fn normalize_char(player: Player, c: char) -> char {
+    let piece = |ct| {
+        chess_name(if player == Player::Red {
+            Chess::Red(ct)
+        } else {
+            Chess::Black(ct)
+        })
+    };
+    match c {
+        '帅' | '帥' | '将' | '將' => piece(ChessType::King),
+        '仕' | '士' => piece(ChessType::Advisor),
+        '相' | '象' => piece(ChessType::Bishop),
+        '兵' | '卒' => piece(ChessType::Pawn),
+        '马' | '馬' | '傌' => '马',
+        '车' | '車' | '俥' => '车',
+        '炮' | '砲' | '包' => '炮',
+        '進' => '进',
+        '後' => '后',
+        _ => {
+            let n = if let Some(i) = RED_NUMBERS
+                .iter()
+                .position(|&x| x == c)
+            {
+                i as i32 + 1
+            } else if ('1'..='9').contains(&c) {
+                c as i32 - '0' as i32
+            } else if ('１'..='９').contains(&c) {
+                c as i32 - '１' as i32 + 1
+            } else {
+                return c;
+            };
+            number_name(player, n)
+        }
+    }
+}
+
+impl Board {
+    // 着法的中文纵线记法，同一纵线上有两个相同的棋子时用前、后区分，
+    // 三个以上的兵卒从前往后编号，红方写一兵、二兵、三兵，黑方写1卒、2卒、3卒
+    pub fn move_to_chinese(&self, m: &Move) -> String {
+        self.move_to_chinese_with(m, true)
+    }
+    // numbered为false时三个以上的同类棋子用前、中、后区分，解析时也接受这种写法
+    fn move_to_chinese_with(&self, m: &Move, numbered: bool) -> String {
+        let player = m.player;
+        let ct = m
+            .chess
+            .chess_type()
+            .unwrap();
+        let mut result = String::new();
+        // 同一纵线上的同类棋子，按从前往后排列
+        let mut rows = (0..10)
+            .filter(|&row| self.chess_at(crate::board::Position::new(row, m.from.col)) == m.chess)
+            .collect::<Vec<_>>();
+        if player == Player::Black {
+            rows.reverse();
+        }
+        if rows.len() >= 2 {
+            let index = rows
+                .iter()
+                .position(|&row| row == m.from.row)
+                .unwrap();
+            result.push(if numbered && rows.len() >= 3 {
+                number_name(player, index as i32 + 1)
+            } else if index == 0 {
+                '前'
+            } else if index == rows.len() - 1 {
+                '后'
+            } else {
+                '中'
+            });
+            result.push(chess_name(m.chess));
+        } else {
+            result.push(chess_name(m.chess));
+            result.push(number_name(player, file_number(player, m.from.col)));
+        }
+        if m.from.row == m.to.row {
+            result.push('平');
+            result.push(number_name(player, file_number(player, m.to.col)));
+            return result;
+        }
+        let forward = (m.to.row < m.from.row) == (player == Player::Red);
+        result.push(if forward { '进' } else { '退' });
+        // 马、仕、相斜着走，记落点的纵线，其余记走的步数
+        let n = match ct {
+            ChessType::Knight | ChessType::Advisor | ChessType::Bishop => {
+                file_number(player, m.to.col)
+            }
+            _ => (m.to.row - m.from.row).abs(),
+        };
+        result.push(number_name(player, n));
+        result
+    }
+    // 着法的WXF记法，棋子用字母，进、退、平写成+、-、.，纵线和步数都用阿拉伯数字，如炮二平五是C2.5；
+    // 同一纵线上的同类棋子在字母后面用+、-区分前后，三个以上时中间的用=，如前炮平五是C+.5
+    pub fn move_to_wxf(&self, m: &Move) -> String {
+        let mut chars = self
+            .move_to_chinese_with(m, false)
+            .chars()
+            .collect::<Vec<_>>();
+        if matches!(chars[0], '前' | '中' | '后') {
+            chars.swap(0, 1);
+        }
+        chars
+            .into_iter()
+            .map(|c| match c {
+                '帅' | '将' => 'K',
+                '仕' | '士' => 'A',
+                '相' | '象' => 'E',
+                '马' => 'H',
+                '车' => 'R',
+                '炮' => 'C',
+                '兵' | '卒' => 'P',
+                '进' | '前' => '+',
+                '退' | '后' => '-',
+                '中' => '=',
+                '平' => '.',
+                _ => RED_NUMBERS
+                    .iter()
+                    .position(|&x| x == c)
+                    .map_or(c, |i| BLACK_NUMBERS[i]),
+            })
+            .collect()
+    }
+    // 解析当前行棋方的中文记法，找不到对应的合法着法时返回None
+    pub fn chinese_to_move(&mut self, s: &str) -> Option<Move> {
+        let player = self.turn;
+        let text = s
+            .trim()
+            .chars()
+            .map(|c| normalize_char(player, c))
+            .collect::<String>();
+        self.generate_move(false)
+            .into_iter()
+            .find(|m| {
+                (self.move_to_chinese_with(m, true) == text
+                    || self.move_to_chinese_with(m, false) == text)
+                    && self.is_move_legal(m)
+            })
+    }
+    // 解析当前行棋方的WXF记法，字母不分大小写，马和相也可以写成N和B，找不到对应的合法着法时返回None
+    pub fn wxf_to_move(&mut self, s: &str) -> Option<Move> {
+        let text = s
+            .trim()
+            .chars()
+            .map(|c| match c.to_ascii_uppercase() {
+                'N' => 'H',
+                'B' => 'E',
+                c => c,
+            })
+            .collect::<String>();
+        self.generate_move(false)
+            .into_iter()
+            .find(|m| self.move_to_wxf(m) == text && self.is_move_legal(m))
+    }
+    // 坐标记法、WXF记法或中文记法的着法，不合法时返回原因
+    fn parse_move_text(&mut self, s: &str) -> Result<Move, String> {
+        if !s.is_ascii() {
+            return self
+                .chinese_to_move(s)
+                .ok_or_else(|| format!("着法{}不合法", s));
+        }
+        if let Some(m) = self.wxf_to_move(s) {
+            return Ok(m);
+        }
+        let (from, to) = parse_move_string(s).map_err(|e| format!("着法{}不合法: {}", s, e))?;
+        let m = Move {
+            player: self.turn,
+            from,
+            to,
+            chess: self.chess_at(from),
+            capture: self.chess_at(to),
+        };
+        if !self.is_move_legal(&m) {
+            return Err(format!("着法{}不合法", s));
+        }
+        Ok(m)
+    }
+    // 依次走一串着法，两种记法可以混用，遇到不合法的着法时停下，
+    // 返回它的序号和原因，之前的着法已经走了
+    pub fn play_moves(&mut self, moves: &[&str]) -> Result<(), (usize, String)> {
+        for (i, s) in moves
+            .iter()
+            .enumerate()
+        {
+            let m = self
+                .parse_move_text(s)
+                .map_err(|e| (i, e))?;
+            self.play_move(&m);
+        }
+        Ok(())
+    }
+    // 导出走过的着法，每回合一行，如"1. 炮二平五 马8进7"，黑方先走时第一回合红方的位置写"..."，
+    // 不是从开局局面开始时，第一行是[FEN "起始局面"]
+    pub fn export_moves(&self, format: MoveFormat) -> String {
+        let mut board = self.clone_position();
+        board.goto_ply(0);
+        let mut lines = vec![];
+        let fen = board.to_fen();
+        if fen != Board::init().to_fen() {
+            lines.push(format!("[FEN \"{}\"]", fen));
+        }
+        let mut number = board.fullmove_number;
+        let mut line = String::new();
+        for m in self
+            .move_history
+            .iter()
+        {
+            let text = match format {
+                MoveFormat::Iccs => m.to_string(),
+                MoveFormat::Chinese => board.move_to_chinese(m),
+            };
+            if m.player == Player::Red {
+                line = format!("{}. {}", number, text);
+            } else {
+                if line.is_empty() {
+                    line = format!("{}. ...", number);
+                }
+                lines.push(format!("{} {}", line, text));
+                line.clear();
+                number += 1;
+            }
+            board.do_move(m);
+        }
+        if !line.is_empty() {
+            lines.push(line);
+        }
+        lines.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{board::Board, notation::MoveFormat};
+
+    #[test]
+    fn test_move_to_chinese() {
+        let mut board = Board::init();
+        let m = board
+            .chinese_to_move("炮二平五")
+            .unwrap();
+        assert_eq!(m.from, "h2".into());
+        assert_eq!(m.to, "e2".into());
+        assert_eq!(board.move_to_chinese(&m), "炮二平五");
+        board.do_move(&m);
+        let m = board
+            .chinese_to_move("马8进7")
+            .unwrap();
+        assert_eq!(m.from, "h9".into());
+        assert_eq!(m.to, "g7".into());
+        // 全角数字和繁体字
+        assert_eq!(board.chinese_to_move("馬８進７"), Some(m.clone()));
+        board.do_move(&m);
+        let m = board
+            .chinese_to_move("兵七进一")
+            .unwrap();
+        assert_eq!(m.from, "c3".into());
+        assert_eq!(m.to, "c4".into());
+        // 不合法的着法
+        assert!(board
+            .chinese_to_move("车一进九")
+            .is_none());
+        assert!(board
+            .chinese_to_move("abc")
+            .is_none());
+    }
+
+    #[test]
+    fn test_chinese_front_and_back() {
+        // 两个红车在同一纵线上
+        let mut board = Board::from_fen("5k3/9/9/9/9/9/4R4/9/4R4/3K5 w - - 0 1");
+        let m = board
+            .chinese_to_move("前车进五")
+            .unwrap();
+        assert_eq!(m.from, "e3".into());
+        assert_eq!(m.to, "e8".into());
+        let m = board
+            .chinese_to_move("后车平一")
+            .unwrap();
+        assert_eq!(m.from, "e1".into());
+        assert_eq!(m.to, "i1".into());
+        assert_eq!(board.move_to_chinese(&m), "后车平一");
+    }
+
+    #[test]
+    fn test_move_to_wxf() {
+        let mut board = Board::init();
+        let m = board
+            .chinese_to_move("炮二平五")
+            .unwrap();
+        assert_eq!(board.move_to_wxf(&m), "C2.5");
+        board.do_move(&m);
+        let m = board
+            .chinese_to_move("马8进7")
+            .unwrap();
+        assert_eq!(board.move_to_wxf(&m), "H8+7");
+        // 前后两个车，三个兵
+        let mut board = Board::from_fen("5k3/9/9/9/9/9/4R4/9/4R4/3K5 w - - 0 1");
+        let m = board
+            .chinese_to_move("后车平一")
+            .unwrap();
+        assert_eq!(board.move_to_wxf(&m), "R-.1");
+        let mut board = Board::from_fen("3k5/9/9/4P4/4P4/4P4/9/9/9/5K3 w - - 0 1");
+        let m = board
+            .chinese_to_move("二兵平四")
+            .unwrap();
+        assert_eq!(board.move_to_wxf(&m), "P=.4");
+        let m = board
+            .chinese_to_move("一兵进一")
+            .unwrap();
+        assert_eq!(board.move_to_wxf(&m), "P++1");
+        // 解析得到同一个着法
+        assert_eq!(board.wxf_to_move("P++1"), Some(m));
+        assert_eq!(board.wxf_to_move("p++1"), board.wxf_to_move("P++1"));
+        assert_eq!(board.wxf_to_move("P++9"), None);
+        let mut board = Board::init();
+        let m = board
+            .chinese_to_move("马二进三")
+            .unwrap();
+        assert_eq!(board.wxf_to_move("H2+3"), Some(m.clone()));
+        assert_eq!(board.wxf_to_move("N2+3"), Some(m));
+        assert_eq!(board.wxf_to_move("h2e2"), None);
+    }
+
+    #[test]
+    fn test_three_pawns_on_file() {
+        // 三个红兵在同一纵线上，从前往后是一兵、二兵、三兵
+        let mut board = Board::from_fen("3k5/9/9/4P4/4P4/4P4/9/9/9/5K3 w - - 0 1");
+        let m = board
+            .chinese_to_move("二兵平四")
+            .unwrap();
+        assert_eq!(m.from, "e5".into());
+        assert_eq!(m.to, "f5".into());
+        assert_eq!(board.move_to_chinese(&m), "二兵平四");
+        // 也可以写成中兵
+        assert_eq!(board.chinese_to_move("中兵平四"), Some(m));
+        let m = board
+            .chinese_to_move("一兵进一")
+            .unwrap();
+        assert_eq!(m.from, "e6".into());
+        assert_eq!(board.move_to_chinese(&m), "一兵进一");
+        // 后面的兵被挡住了
+        assert_eq!(board.chinese_to_move("三兵进一"), None);
+
+        // 黑方从自己的前面（棋盘下方）数起
+        let mut board = Board::from_fen("3k5/9/9/9/9/4p4/4p4/4p4/9/5K3 b - - 0 1");
+        let m = board
+            .chinese_to_move("2卒平4")
+            .unwrap();
+        assert_eq!(m.from, "e3".into());
+        assert_eq!(m.to, "d3".into());
+        assert_eq!(board.move_to_chinese(&m), "2卒平4");
+        let m = board
+            .chinese_to_move("1卒进1")
+            .unwrap();
+        assert_eq!(m.from, "e2".into());
+        assert_eq!(m.to, "e1".into());
+    }
+
+    #[test]
+    fn test_export_moves() {
+        // 导出的着法去掉回合序号后可以再走一遍
+        let import = |text: &str| {
+            let mut lines = text.lines();
+            let mut board = Board::init();
+            let mut first = lines.next();
+            if let Some(fen) = first.and_then(|l| l.strip_prefix("[FEN \"")) {
+                board = Board::from_fen(fen.trim_end_matches("\"]"));
+                first = None;
+            }
+            let moves = first
+                .into_iter()
+                .chain(lines)
+                .flat_map(|l| l.split_whitespace())
+                .filter(|t| !t.ends_with('.') && *t != "...")
+                .collect::<Vec<_>>();
+            board
+                .play_moves(&moves)
+                .unwrap();
+            board
+        };
+        let mut board = Board::init();
+        board
+            .play_moves(&["h2e2", "h9g7", "h0g2", "i9h9", "i0h0"])
+            .unwrap();
+        let text = board.export_moves(MoveFormat::Iccs);
+        assert_eq!(text, "1. h2e2 h9g7\n2. h0g2 i9h9\n3. i0h0");
+        assert_eq!(import(&text).to_fen(), board.to_fen());
+        let text = board.export_moves(MoveFormat::Chinese);
+        assert_eq!(text, "1. 炮二平五 马8进7\n2. 马二进三 车9平8\n3. 车一平二");
+        assert_eq!(import(&text).to_fen(), board.to_fen());
+
+        // 黑方先走的局面
+        let mut board = Board::from_fen("4k4/9/9/9/9/9/9/9/4R4/3K5 b - - 0 5");
+        board
+            .play_moves(&["e9f9", "e1f1", "f9e9"])
+            .unwrap();
+        let text = board.export_moves(MoveFormat::Chinese);
+        assert_eq!(
+            text,
+            "[FEN \"4k4/9/9/9/9/9/9/9/4R4/3K5 b - - 0 5\"]\n5. ... 将5平6\n6. 车五平四 将6平5"
+        );
+        assert_eq!(import(&text).to_fen(), board.to_fen());
+        assert_eq!(Board::init().export_moves(MoveFormat::Iccs), "");
+    }
+
+    #[test]
+    fn test_play_moves() {
+        let mut board = Board::init();
+        assert!(board
+            .play_moves(&["h2e2", "马8进7", "h0g2", "i9h9"])
+            .is_ok());
+        assert_eq!(
+            board.to_fen(),
+            "rnbakabr1/9/1c4nc1/p1p1p1p1p/9/9/P1P1P1P1P/1C2C1N2/9/RNBAKAB1R w - - 4 3"
+        );
+
+        // 第3步的车走不过去，之前的着法已经走了
+        let mut board = Board::init();
+        let (index, reason) = board
+            .play_moves(&["h2e2", "h9g7", "a0a5", "b9c7"])
+            .unwrap_err();
+        assert_eq!(index, 2);
+        assert!(reason.contains("a0a5"));
+        assert_eq!(
+            board
+                .move_history
+                .len(),
+            2
+        );
+        assert_eq!(
+            board
+                .play_moves(&["b0c2", "z9"])
+                .unwrap_err()
+                .0,
+            1
+        );
+        assert_eq!(
+            board
+                .play_moves(&["车1进9"])
+                .unwrap_err()
+                .0,
+            0
+        );
+    }
+}
@@ -0,0 +1,109 @@
+use std::sync::Mutex;
+
+use crate::board::{Move, Player};
+
+// 置换表里记录的分数类型
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum RecordFlag {
+    Exact, // 准确值
+    Lower, // 发生了beta截断，真实分数不低于记录的分数
+    Upper, // 没有着法超过alpha，真实分数不高于记录的分数
+}
+
+#[derive(Clone, Debug)]
+pub struct Record {
+    pub value: i32,
+    pub depth: i32,
+    pub flag: RecordFlag,
+    pub best_move: Option<Move>,
+    pub zobrist_lock: u64,
+    pub turn: Player,
+}
+
+const SHARD_COUNT: usize = 64;
+
+// 置换表，分片加锁，多个搜索线程可以共享同一张表
+pub struct TranspositionTable {
+    shards: Vec<Mutex<Vec<Option<Record>>>>,
+    shard_size: usize,
+}
+
+impl TranspositionTable {
+    pub fn new(size: usize) -> Self {
+        let shard_size = size / SHARD_COUNT;
+        let shard_count = if shard_size > 0 { SHARD_COUNT } else { 0 };
+        TranspositionTable {
+            shards: (0..shard_count)
+                .map(|_| Mutex::new(vec![None; shard_size]))
+                .collect(),
+            shard_size,
+        }
+    }
+    // 按占用的内存(MB)确定记录数，0MB的置换表不记录任何局面
+    pub fn with_megabytes(megabytes: usize) -> Self {
+        TranspositionTable::new(megabytes * 1024 * 1024 / std::mem::size_of::<Option<Record>>())
+    }
+    // 大小为0的置换表，不记录任何局面
+    pub fn is_empty(&self) -> bool {
+        self.shards
+            .is_empty()
+    }
+    fn slot(&self, zobrist_value: u64) -> (usize, usize) {
+        let shard = (zobrist_value % SHARD_COUNT as u64) as usize;
+        let index = (zobrist_value / SHARD_COUNT as u64) % self.shard_size as u64;
+        (shard, index as usize)
+    }
+    pub fn find(&self, zobrist_value: u64, zobrist_lock: u64, turn: Player) -> Option<Record> {
+        if self.is_empty() {
+            return None;
+        }
+        let (shard, index) = self.slot(zobrist_value);
+        let records = self.shards[shard]
+            .lock()
+            .unwrap();
+        match &records[index] {
+            Some(record) if record.zobrist_lock == zobrist_lock && record.turn == turn => {
+                Some(record.clone())
+            }
+            _ => None,
+        }
+    }
+    pub fn add(&self, zobrist_value: u64, record: Record) {
+        if self.is_empty() {
+            return;
+        }
+        let (shard, index) = self.slot(zobrist_value);
+        let mut records = self.shards[shard]
+            .lock()
+            .unwrap();
+        // 同一局面用搜索深度较大的覆盖，不同局面直接替换
+        let replace = match &records[index] {
+            Some(old) => {
+                old.zobrist_lock != record.zobrist_lock
+                    || old.turn != record.turn
+                    || record.depth >= old.depth
+            }
+            None => true,
+        };
+        if replace {
+            records[index] = Some(record);
+        }
+    }
+    pub fn clear(&self) {
+        for shard in self.shards.iter() {
+            for record in shard
+                .lock()
+                .unwrap()
+                .iter_mut()
+            {
+                *record = None;
+            }
+        }
+    }
+}
+
+impl Default for TranspositionTable {
+    fn default() -> Self {
+        TranspositionTable::new(0)
+    }
+}
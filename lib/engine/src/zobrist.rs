@@ -1,11 +1,11 @@
-use crate::board::{Chess, Move, BOARD_HEIGHT, BOARD_WIDTH};
+use crate::board::{Chess, Move, Position, BOARD_HEIGHT, BOARD_SQUARES, BOARD_WIDTH};
 
 #[derive(Debug)]
 pub struct Zobristable {
-    hash_table: [[[u64; 7]; 90]; 2],
+    hash_table: [[[u64; 7]; BOARD_SQUARES]; 2],
 }
 
-fn rand64() -> u64 {
+pub(crate) fn rand64() -> u64 {
     let mut buf = [0; 8];
     getrandom::getrandom(&mut buf).unwrap();
     let mut value = 0;
@@ -15,15 +15,30 @@ fn rand64() -> u64 {
     value
 }
 
+// splitmix64，种子相同时生成的序列相同
+pub(crate) fn next_seeded(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9E3779B97F4A7C15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+    z ^ (z >> 31)
+}
+
 impl Zobristable {
+    // 每次用新的随机种子，两张表互不相关
     pub fn new() -> Self {
+        Zobristable::with_seed(rand64())
+    }
+    // 固定种子生成，方便测试复现
+    pub fn with_seed(seed: u64) -> Self {
         let mut z = Zobristable {
-            hash_table: [[[0u64; 7]; 90]; 2],
+            hash_table: [[[0u64; 7]; BOARD_SQUARES]; 2],
         };
+        let mut state = seed;
         for l in 0..2 {
-            for m in 0..90 {
+            for m in 0..BOARD_SQUARES {
                 for n in 0..7 {
-                    z.hash_table[l][m][n] = rand64();
+                    z.hash_table[l][m][n] = next_seeded(&mut state);
                 }
             }
         }
@@ -41,7 +56,7 @@ impl Zobristable {
                     value ^= self.hash_table[chess
                         .player()
                         .unwrap()
-                        .value() as usize][(i * BOARD_WIDTH + j) as usize]
+                        .value() as usize][Position::new(i, j).to_index()]
                         [ct.value() as usize];
                 }
             }
@@ -55,23 +70,21 @@ impl Zobristable {
             .chess
             .player()
             .unwrap()
-            .value() as usize][(m.from.row * BOARD_WIDTH + m.from.col) as usize][m
+            .value() as usize][m.from.to_index()][m
             .chess
             .chess_type()
             .unwrap()
-            .value()
-            as usize];
+            .value() as usize];
         // 放到新的位置
         value ^= self.hash_table[m
             .chess
             .player()
             .unwrap()
-            .value() as usize][(m.to.row * BOARD_WIDTH + m.to.col) as usize][m
+            .value() as usize][m.to.to_index()][m
             .chess
             .chess_type()
             .unwrap()
-            .value()
-            as usize];
+            .value() as usize];
         // 如果有吃子，把被吃掉的子拿起来
         if let Some(ct) = m
             .capture
@@ -81,8 +94,7 @@ impl Zobristable {
                 .capture
                 .player()
                 .unwrap()
-                .value() as usize][(m.to.row * BOARD_WIDTH + m.to.col) as usize]
-                [ct.value() as usize];
+                .value() as usize][m.to.to_index()][ct.value() as usize];
         }
         value
     }
@@ -120,4 +132,39 @@ mod test {
         let hash_after = zorbis_table.undo_move(zorbis_table.apply_move(hash, &m), &m);
         assert_eq!(hash, hash_after);
     }
+
+    #[test]
+    fn test_zobrist_with_seed() {
+        let chesses = Board::init().chesses;
+        assert_eq!(
+            Zobristable::with_seed(1).calc_chesses(&chesses),
+            Zobristable::with_seed(1).calc_chesses(&chesses)
+        );
+        assert_ne!(
+            Zobristable::with_seed(1).calc_chesses(&chesses),
+            Zobristable::with_seed(2).calc_chesses(&chesses)
+        );
+    }
+
+    #[test]
+    fn test_zobrist_lock_independent() {
+        // 校验值和主值来自不同的表，不同局面的两组值不会一样
+        let mut board = Board::init();
+        for _ in 0..20 {
+            let m = board
+                .generate_move(false)
+                .pop()
+                .unwrap();
+            board.do_move(&m);
+            assert_ne!(board.zobrist_value, board.zobrist_value_lock);
+            assert_eq!(
+                board.zobrist_value,
+                crate::constant::ZOBRIST_TABLE.calc_chesses(&board.chesses)
+            );
+            assert_eq!(
+                board.zobrist_value_lock,
+                crate::constant::ZOBRIST_TABLE_LOCK.calc_chesses(&board.chesses)
+            );
+        }
+    }
 }
@@ -1,6 +1,20 @@
 use crate::game::Turn::{Black, Red};
+use engine::board::{self, Board, Player};
+use engine::constant::{MAX, MIN, RECORD_SIZE};
+use engine::difficulty::Difficulty;
+use engine::record::TranspositionTable;
+use std::sync::Arc;
 use ChessType::*;
 
+// 开局变化 前几步在分数相差不超过OPENING_VARIETY_MARGIN的前几个着法中随机选
+const OPENING_VARIETY_PLIES: usize = 6;
+const OPENING_VARIETY_TOP_N: usize = 3;
+const OPENING_VARIETY_MARGIN: i32 = 20;
+// 开局变化要给每个着法单独打分，比一次搜索慢得多，深度不超过这个值，免得界面卡住
+const OPENING_VARIETY_DEPTH: i32 = 4;
+// 教练模式 刚走的一步比最好的着法差COACH_MARGIN分以上时提醒
+const COACH_MARGIN: i32 = 30;
+
 #[derive(PartialEq, Debug, Clone, Copy)]
 pub struct Position {
     pub x: i32,
@@ -174,6 +188,23 @@ impl Chess {
         // 两个九宫格区域
         (3..=5).contains(&x) && ((0..=2).contains(&y) || (7..=9).contains(&y))
     }
+    // 引擎FEN里的棋子字母，红方大写
+    fn fen_char(&self) -> char {
+        let c = match self.chess_type {
+            车 => 'r',
+            马 => 'n',
+            象 => 'b',
+            士 => 'a',
+            帅 => 'k',
+            炮 => 'c',
+            兵 => 'p',
+        };
+        if self.turn == Red {
+            c.to_ascii_uppercase()
+        } else {
+            c
+        }
+    }
     pub fn name_str(&self) -> &'static str {
         match self.chess_type {
             车 => "车",
@@ -198,11 +229,28 @@ impl From<(ChessType, Turn, (i32, i32))> for Chess {
     }
 }
 
+// 走过的一步棋
+#[derive(Debug)]
+struct Step {
+    turn: Turn,
+    from: Position,
+    to: Position,
+    eaten: Option<Chess>, // 这一步吃掉的棋子
+}
+
 pub struct ChineseChess {
-    pub chessmen: Vec<Chess>,                 // 棋盘上的棋子
-    selected: Option<usize>,                  // 当前选中的棋子序号
-    cur_turn: Turn,                           // 当前走棋方
-    history: Vec<(Turn, Position, Position)>, // 历史记录 方便撤回
+    pub chessmen: Vec<Chess>,                      // 棋盘上的棋子
+    selected: Option<usize>,                       // 当前选中的棋子序号
+    cur_turn: Turn,                                // 当前走棋方
+    history: Vec<Step>,                            // 历史记录 方便撤回
+    redo_history: Vec<(Turn, Position, Position)>, // 悔掉的棋 方便重做
+    pub hint: Option<(Position, Position)>,        // 提示的着法 只标出来不走
+    pub opening_variety: bool,                     // 开局几步在分数接近的着法中随机提示
+    start_fen: String,                             // 开局或载入的局面 用来重放历史记录
+    pub flipped: bool,                             // 棋盘上下颠倒显示 只影响画面
+    pub coach: bool,                               // 教练模式 每走一步检查是否有更好的着法
+    search: Option<Board>,                         // 提示用的搜索 整盘棋共用置换表 载入局面时重建
+    pub difficulty: Difficulty,                    // 提示的难度 决定搜索深度和思考时间
 }
 impl ChineseChess {
     fn has_chess(&self, pos: &Position) -> bool {
@@ -250,27 +298,253 @@ impl ChineseChess {
         if let Some(selected) = self.selected {
             let chess = &self.chessmen[selected];
             if chess.can_move_to(&pos, &self) {
-                self.history
-                    .push((self.cur_turn, chess.position, pos.clone()));
+                let from = chess.position;
                 let chess = &mut self.chessmen[selected];
                 chess.position = pos.clone();
+                let eaten = eat_chess.map(|idx| {
+                    self.chessmen
+                        .remove(idx)
+                });
+                self.history
+                    .push(Step {
+                        turn: self.cur_turn,
+                        from,
+                        to: pos.clone(),
+                        eaten,
+                    });
+                self.redo_history
+                    .clear();
                 self.cur_turn = match self.cur_turn {
                     Red => Black,
                     Black => Red,
                 }; // 改变走棋方
                 self.selected = None;
-                if let Some(idx) = eat_chess {
-                    self.chessmen
-                        .remove(idx);
-                }
+                self.hint = None;
                 return;
             }
         }
     }
+    // 悔棋 撤回上一步
+    pub fn undo(&mut self) -> bool {
+        if let Some(step) = self.history.pop() {
+            if let Some(chess) = self
+                .chessmen
+                .iter_mut()
+                .find(|c| c.position == step.to)
+            {
+                chess.position = step.from;
+            }
+            // 被吃的子放回棋盘
+            if let Some(eaten) = step.eaten {
+                self.chessmen
+                    .push(eaten);
+            }
+            self.cur_turn = step.turn;
+            self.selected = None;
+            self.hint = None;
+            self.redo_history
+                .push((step.turn, step.from, step.to));
+            return true;
+        }
+        return false;
+    }
+    // 重做 恢复最近悔掉的一步
+    pub fn redo(&mut self) -> bool {
+        if let Some((_turn, from, to)) = self
+            .redo_history
+            .pop()
+        {
+            // move_to 会清空重做记录 先保存下来
+            let redo_history = std::mem::take(&mut self.redo_history);
+            self.select(&from);
+            self.move_to(&to);
+            self.redo_history = redo_history;
+            return true;
+        }
+        return false;
+    }
+    // 转换成引擎用的FEN 黑方在上 y对应引擎的行 x对应列
+    fn to_fen(&self) -> String {
+        let mut rows = vec![];
+        for y in 0..10 {
+            let mut row = String::new();
+            let mut empty = 0;
+            for x in 0..9 {
+                if let Some(chess) = self.get_chess(&Position { x, y }) {
+                    if empty > 0 {
+                        row.push_str(&empty.to_string());
+                        empty = 0;
+                    }
+                    row.push(chess.fen_char());
+                } else {
+                    empty += 1;
+                }
+            }
+            if empty > 0 {
+                row.push_str(&empty.to_string());
+            }
+            rows.push(row);
+        }
+        let turn = if self.cur_turn == Red { "w" } else { "b" };
+        format!("{} {} - - 0 1", rows.join("/"), turn)
+    }
+    // 从FEN载入局面 FEN不合法时返回错误信息 局面不变 记下整理过的FEN 多余的空格不会带到重放里
+    pub fn load_fen(&mut self, fen: &str) -> Result<(), String> {
+        let board = Board::try_from_fen(fen).map_err(|e| e.to_string())?;
+        let mut chessmen = vec![];
+        for (y, row) in board
+            .chesses
+            .iter()
+            .enumerate()
+        {
+            for (x, chess) in row
+                .iter()
+                .enumerate()
+            {
+                let (turn, ct) = match chess {
+                    board::Chess::Red(ct) => (Red, ct),
+                    board::Chess::Black(ct) => (Black, ct),
+                    board::Chess::None => continue,
+                };
+                let chess_type = match ct {
+                    board::ChessType::Rook => 车,
+                    board::ChessType::Knight => 马,
+                    board::ChessType::Bishop => 象,
+                    board::ChessType::Advisor => 士,
+                    board::ChessType::King => 帅,
+                    board::ChessType::Cannon => 炮,
+                    board::ChessType::Pawn => 兵,
+                };
+                chessmen.push((chess_type, turn, (x as i32, y as i32)).into());
+            }
+        }
+        *self = ChineseChess {
+            chessmen,
+            cur_turn: if board.turn == Player::Red {
+                Red
+            } else {
+                Black
+            },
+            opening_variety: self.opening_variety,
+            coach: self.coach,
+            start_fen: board.to_fen(),
+            flipped: self.flipped,
+            difficulty: self.difficulty,
+            ..Default::default()
+        };
+        Ok(())
+    }
+    // 棋盘坐标和画面坐标互相转换 翻转时旋转180度 转换两次回到原来的坐标
+    pub fn view_position(&self, pos: &Position) -> Position {
+        if self.flipped {
+            Position {
+                x: 8 - pos.x,
+                y: 9 - pos.y,
+            }
+        } else {
+            *pos
+        }
+    }
+    // 上一步的起点和落点 界面据此标出上一步
+    pub fn last_move(&self) -> Option<(Position, Position)> {
+        self.history
+            .last()
+            .map(|step| (step.from, step.to))
+    }
+    // 从开局或载入的局面重放历史记录 得到带走棋历史的引擎棋盘
+    fn engine_board(&self) -> Board {
+        let mut board = Board::from_fen(&self.start_fen);
+        for step in self.history.iter() {
+            let from = board::Position::new(step.from.y, step.from.x);
+            let to = board::Position::new(step.to.y, step.to.x);
+            let m = board::Move {
+                player: board.turn,
+                from,
+                to,
+                chess: board.chess_at(from),
+                capture: board.chess_at(to),
+            };
+            board.play_move(&m);
+        }
+        board
+    }
+    // 实际对局中同一局面出现了三次 判和
+    pub fn is_threefold_repetition(&self) -> bool {
+        self.engine_board()
+            .is_threefold_repetition()
+    }
+    // 双方的子力 (红方, 黑方)
+    pub fn material(&self) -> (i32, i32) {
+        let board = Board::from_fen(&self.to_fen());
+        (board.material(Player::Red), board.material(Player::Black))
+    }
+    // 提示 让引擎按选定的难度给当前走棋方搜一步棋
+    pub fn hint(&mut self) -> bool {
+        let kings = self
+            .chessmen
+            .iter()
+            .filter(|c| c.chess_type == 帅)
+            .count();
+        if kings != 2 {
+            // 有一方的帅被吃了 棋局已经结束
+            return false;
+        }
+        // 开局的前几步在分数接近的着法中随机选 每盘棋走得不一样 之后按最佳着法提示
+        let variety = self.opening_variety && self.history.len() < OPENING_VARIETY_PLIES;
+        let difficulty = self.difficulty;
+        let game = self.engine_board();
+        let board = self.search_board(&game);
+        let m = if variety {
+            board.pick_near_best(
+                difficulty
+                    .max_depth()
+                    .min(OPENING_VARIETY_DEPTH),
+                OPENING_VARIETY_TOP_N,
+                OPENING_VARIETY_MARGIN,
+            )
+        } else {
+            board.search_by_difficulty(difficulty)
+        };
+        self.hint = m.map(|m| {
+            (
+                Position {
+                    x: m.from.col,
+                    y: m.from.row,
+                },
+                Position {
+                    x: m.to.col,
+                    y: m.to.row,
+                },
+            )
+        });
+        self.hint.is_some()
+    }
+    // 教练模式 用走上一步之前的局面搜索 上一步的分数比最好的着法低COACH_MARGIN以上时返回true
+    pub fn is_last_move_worse(&mut self, depth: i32) -> bool {
+        let mut game = self.engine_board();
+        let Some(m) = game.undo_last() else {
+            return false;
+        };
+        let board = self.search_board(&game);
+        let (best, _) = board.alpha_beta_pvs(depth, MIN, MAX);
+        best - board.evaluate_move(&m, depth) > COACH_MARGIN
+    }
+    // 每次搜索前把局面和走棋历史同步成实际对局 置换表和历史表留着下次用
+    fn search_board(&mut self, game: &Board) -> &mut Board {
+        let board = self
+            .search
+            .get_or_insert_with(|| {
+                let mut board = Board::init();
+                board.records = Arc::new(TranspositionTable::new(RECORD_SIZE));
+                board
+            });
+        board.sync_position(game);
+        board
+    }
     #[allow(dead_code)]
     fn replay_history(&mut self) {
         let old = std::mem::replace(self, ChineseChess::default());
-        for (_a, _b, _c) in old.history {}
+        for _step in old.history {}
     }
 }
 impl Default for ChineseChess {
@@ -298,11 +572,123 @@ impl Default for ChineseChess {
         .flat_map(|(c, (x, y))| [(c, Black, (x, y)), (c, Red, (8 - x, 9 - y))])
         .map(Into::into)
         .collect();
-        return ChineseChess {
+        let mut game = ChineseChess {
             chessmen,
             cur_turn: Turn::Red,
             history: Default::default(),
+            redo_history: Default::default(),
             selected: Default::default(),
+            hint: Default::default(),
+            opening_variety: Default::default(),
+            start_fen: Default::default(),
+            flipped: Default::default(),
+            coach: Default::default(),
+            search: Default::default(),
+            difficulty: Difficulty::Medium,
         };
+        game.start_fen = game.to_fen();
+        return game;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::game::{ChineseChess, Position};
+    use engine::difficulty::Difficulty;
+
+    #[test]
+    fn test_view_position() {
+        let mut game = ChineseChess::default();
+        let pos = Position { x: 1, y: 9 };
+        assert_eq!(game.view_position(&pos), pos);
+        game.flipped = true;
+        let view = game.view_position(&pos);
+        assert_eq!(view, Position { x: 7, y: 0 });
+        // 点击画面上的位置 换回棋盘坐标后是同一个子
+        assert_eq!(game.view_position(&view), pos);
+        game.click(&game.view_position(&view));
+        game.click(&game.view_position(&Position { x: 8 - 2, y: 9 - 7 }));
+        assert_eq!(game.last_move(), Some((pos, Position { x: 2, y: 7 })));
+    }
+
+    #[test]
+    fn test_is_last_move_worse() {
+        let mut game = ChineseChess::default();
+        assert!(!game.is_last_move_worse(3));
+        // 红车吃掉送上门的黑车是好棋 车走开不吃是坏棋
+        let fen = "4k4/9/9/9/4r4/9/9/9/4R4/3K5 w - - 0 1";
+        game.load_fen(fen)
+            .unwrap();
+        game.click(&Position { x: 4, y: 8 });
+        game.click(&Position { x: 4, y: 4 });
+        assert!(!game.is_last_move_worse(3));
+        game.load_fen(fen)
+            .unwrap();
+        game.click(&Position { x: 4, y: 8 });
+        game.click(&Position { x: 0, y: 8 });
+        assert!(game.is_last_move_worse(3));
+    }
+
+    #[test]
+    fn test_threefold_repetition() {
+        let mut game = ChineseChess::default();
+        // 双方的马跳出去再跳回来 走两遍回到开局局面三次
+        let steps = [
+            ((1, 9), (2, 7)),
+            ((1, 0), (2, 2)),
+            ((2, 7), (1, 9)),
+            ((2, 2), (1, 0)),
+        ];
+        for _ in 0..2 {
+            assert!(!game.is_threefold_repetition());
+            for ((x1, y1), (x2, y2)) in steps {
+                game.click(&Position { x: x1, y: y1 });
+                game.click(&Position { x: x2, y: y2 });
+            }
+        }
+        assert!(game.is_threefold_repetition());
+        // 悔棋后局面变了 不再是三次重复 界面据此清掉和棋提示
+        assert!(game.undo());
+        assert!(!game.is_threefold_repetition());
+        assert!(game.redo());
+        assert!(game.is_threefold_repetition());
+    }
+
+    #[test]
+    fn test_load_fen_whitespace() {
+        let mut game = ChineseChess::default();
+        // 开头和中间有多余的空白 重放用的局面也是这个局面
+        game.load_fen(" 4k4/9/9/9/4r4/9/9/9/4R4/3K5\tw  - - 0 1")
+            .unwrap();
+        assert_eq!(
+            game.engine_board()
+                .to_fen(),
+            game.to_fen()
+        );
+        assert!(game.hint());
+        assert_eq!(
+            game.hint,
+            Some((Position { x: 4, y: 8 }, Position { x: 4, y: 4 }))
+        );
+    }
+
+    #[test]
+    fn test_hint_difficulty() {
+        let mut game = ChineseChess::default();
+        assert_eq!(game.difficulty, Difficulty::Medium);
+        // 送上门的车 每个难度都提示吃掉
+        let fen = "4k4/9/9/9/4r4/9/9/9/4R4/3K5 w - - 0 1";
+        for difficulty in [Difficulty::Easy, Difficulty::Medium, Difficulty::Hard] {
+            game.difficulty = difficulty;
+            game.load_fen(fen)
+                .unwrap();
+            // 载入局面后难度不变
+            assert_eq!(game.difficulty, difficulty);
+            assert!(game.hint());
+            assert_eq!(
+                game.hint,
+                Some((Position { x: 4, y: 8 }, Position { x: 4, y: 4 }))
+            );
+        }
     }
 }
@@ -1,11 +1,16 @@
+use std::{cell::RefCell, rc::Rc};
+
 use crate::game::{self, Turn};
+use engine::difficulty::Difficulty;
 use fltk::{
     app,
-    button::Button,
+    button::{Button, CheckButton},
     enums::*,
     frame::Frame,
     group::*,
     image::{JpegImage, SharedImage},
+    input::Input,
+    menu::Choice,
     prelude::*,
     window::*,
 };
@@ -13,7 +18,9 @@ use fltk::{
 const CHESS_SIZE: i32 = 57;
 const CHESS_BOARD_WIDTH: i32 = 521;
 const CHESS_BOARD_HEIGHT: i32 = 577;
-pub fn ui(mut game: game::ChineseChess) -> anyhow::Result<()> {
+// 教练模式检查每一步的搜索深度 浅一些 走棋时不会卡住
+const COACH_DEPTH: i32 = 3;
+pub fn ui(game: game::ChineseChess) -> anyhow::Result<()> {
     let app = app::App::default().with_scheme(app::Scheme::Oxy);
     let pand = 1;
     let mut top_window = Window::new(
@@ -41,10 +48,28 @@ pub fn ui(mut game: game::ChineseChess) -> anyhow::Result<()> {
     let mut group = Group::default_fill();
     flex.fixed(&group, CHESS_BOARD_WIDTH);
 
-    fn redrawn(group: &mut Group, game: &game::ChineseChess) {
+    fn redrawn(group: &mut Group, material: &mut Frame, game: &game::ChineseChess) {
+        // 子力条 哪方子力多就显示哪方的颜色
+        let (red, black) = game.material();
+        material.set_label(&format!("子力 {}:{}", red, black));
+        material.set_color(if red > black {
+            Color::Red
+        } else if red < black {
+            Color::Blue
+        } else {
+            Color::White
+        });
+        material.set_label_color(if red == black {
+            Color::Black
+        } else {
+            Color::White
+        });
+        material.redraw();
+
         for chess in game.chessmen.iter() {
-            let x = (chess.position.x + 1) * CHESS_SIZE - CHESS_SIZE / 2 - 24;
-            let y = (chess.position.y + 1) * CHESS_SIZE - CHESS_SIZE / 2 - 24;
+            let view = game.view_position(&chess.position);
+            let x = (view.x + 1) * CHESS_SIZE - CHESS_SIZE / 2 - 24;
+            let y = (view.y + 1) * CHESS_SIZE - CHESS_SIZE / 2 - 24;
             let padding = 4;
             let mut button = Button::new(
                 x + padding,
@@ -62,34 +87,242 @@ pub fn ui(mut game: game::ChineseChess) -> anyhow::Result<()> {
             button.set_frame(FrameType::RoundedBox);
             button.set_selection_color(Color::DarkBlue);
             button.set_color(Color::White);
+            // 上一步走的子标浅蓝
+            if let Some((_, to)) = game.last_move() {
+                if chess.position == to {
+                    button.set_color(Color::from_rgb(200, 225, 255));
+                }
+            }
+            // 提示的着法 起点和要吃的子标黄
+            if let Some((from, to)) = game.hint {
+                if chess.position == from || chess.position == to {
+                    button.set_color(Color::Yellow);
+                }
+            }
             group.add(&button);
         }
+        // 提示的落点是空位时画一个黄点
+        if let Some((_, to)) = game.hint {
+            if !game
+                .chessmen
+                .iter()
+                .any(|c| c.position == to)
+            {
+                let size = 16;
+                let to = game.view_position(&to);
+                let x = (to.x + 1) * CHESS_SIZE - CHESS_SIZE / 2 - 24 + CHESS_SIZE / 2 - size / 2;
+                let y = (to.y + 1) * CHESS_SIZE - CHESS_SIZE / 2 - 24 + CHESS_SIZE / 2 - size / 2;
+                let mut dot = Frame::new(x, y, size, size, "");
+                dot.set_frame(FrameType::OvalBox);
+                dot.set_color(Color::Yellow);
+                group.add(&dot);
+            }
+        }
+    }
+
+    // 同一局面出现三次时提示和棋 否则清掉提示 返回是否三次重复局面
+    fn show_repetition(status: &mut Frame, game: &game::ChineseChess) -> bool {
+        let repetition = game.is_threefold_repetition();
+        status.set_label(if repetition {
+            "同一局面出现三次 和棋"
+        } else {
+            ""
+        });
+        repetition
     }
 
-    redrawn(&mut group, &game);
-    chess_window.handle(move |w, event| {
-        if let Event::Push = event {
-            let (click_x, click_y) = app::event_coords();
-            let (x, y) = (click_x / CHESS_SIZE, click_y / CHESS_SIZE);
-            dbg!(x, y);
-            // 点击棋盘
-            game.click(&game::Position { x, y });
-            group.clear();
-            w.redraw();
+    let mut material = Frame::default().with_size(0, 30);
+    material.set_frame(FrameType::FlatBox);
 
-            redrawn(&mut group, &game);
-            return true;
-        }
-        return false;
-    });
+    let game = Rc::new(RefCell::new(game));
+    redrawn(&mut group, &mut material, &game.borrow());
     let mut hpack = Pack::default_fill();
     flex.add(&hpack);
     hpack.set_type(PackType::Vertical);
     hpack.set_spacing(10);
-    Button::default().with_label("悔棋");
-    Button::default().with_label("功能");
-    Button::default().with_label("功能");
-    Button::default().with_label("功能");
+    hpack.add(&material);
+    // 状态栏 先建好给各按钮用 后面再放到侧栏最下面
+    let mut status = Frame::default().with_size(0, 60);
+    status.set_label_size(12);
+    status.set_align(Align::Wrap | Align::Inside);
+    Button::default()
+        .with_label("悔棋")
+        .set_callback({
+            let game = game.clone();
+            let mut group = group.clone();
+            let mut chess_window = chess_window.clone();
+            let mut material = material.clone();
+            let mut status = status.clone();
+            move |_| {
+                if game
+                    .borrow_mut()
+                    .undo()
+                {
+                    group.clear();
+                    chess_window.redraw();
+                    redrawn(&mut group, &mut material, &game.borrow());
+                    show_repetition(&mut status, &game.borrow());
+                }
+            }
+        });
+    Button::default()
+        .with_label("重做")
+        .set_callback({
+            let game = game.clone();
+            let mut group = group.clone();
+            let mut chess_window = chess_window.clone();
+            let mut material = material.clone();
+            let mut status = status.clone();
+            move |_| {
+                if game
+                    .borrow_mut()
+                    .redo()
+                {
+                    group.clear();
+                    chess_window.redraw();
+                    redrawn(&mut group, &mut material, &game.borrow());
+                    show_repetition(&mut status, &game.borrow());
+                }
+            }
+        });
+    Button::default()
+        .with_label("提示")
+        .set_callback({
+            let game = game.clone();
+            let mut group = group.clone();
+            let mut chess_window = chess_window.clone();
+            let mut material = material.clone();
+            move |_| {
+                if game
+                    .borrow_mut()
+                    .hint()
+                {
+                    group.clear();
+                    chess_window.redraw();
+                    redrawn(&mut group, &mut material, &game.borrow());
+                }
+            }
+        });
+    // 提示的难度 默认中等
+    let mut difficulty = Choice::default().with_size(0, 30);
+    difficulty.add_choice("简单|中等|困难");
+    difficulty.set_value(1);
+    difficulty.set_callback({
+        let game = game.clone();
+        move |c| {
+            game.borrow_mut()
+                .difficulty = match c.value() {
+                0 => Difficulty::Easy,
+                2 => Difficulty::Hard,
+                _ => Difficulty::Medium,
+            }
+        }
+    });
+    CheckButton::default()
+        .with_label("开局变化")
+        .set_callback({
+            let game = game.clone();
+            move |b| {
+                game.borrow_mut()
+                    .opening_variety = b.is_checked()
+            }
+        });
+    CheckButton::default()
+        .with_label("教练模式")
+        .set_callback({
+            let game = game.clone();
+            move |b| {
+                game.borrow_mut()
+                    .coach = b.is_checked()
+            }
+        });
+    // 只翻转画面 不影响对局
+    CheckButton::default()
+        .with_label("翻转棋盘")
+        .set_callback({
+            let game = game.clone();
+            let mut group = group.clone();
+            let mut chess_window = chess_window.clone();
+            let mut material = material.clone();
+            move |b| {
+                game.borrow_mut()
+                    .flipped = b.is_checked();
+                group.clear();
+                chess_window.redraw();
+                redrawn(&mut group, &mut material, &game.borrow());
+            }
+        });
+    // 输入FEN载入任意局面
+    let fen_input = Input::default().with_size(0, 30);
+    Button::default()
+        .with_label("载入局面")
+        .set_callback({
+            let game = game.clone();
+            let mut group = group.clone();
+            let mut chess_window = chess_window.clone();
+            let mut material = material.clone();
+            let fen_input = fen_input.clone();
+            let mut status = status.clone();
+            move |_| {
+                let result = game
+                    .borrow_mut()
+                    .load_fen(&fen_input.value());
+                match result {
+                    Ok(()) => {
+                        status.set_label("");
+                        group.clear();
+                        chess_window.redraw();
+                        redrawn(&mut group, &mut material, &game.borrow());
+                    }
+                    Err(e) => status.set_label(&e),
+                }
+            }
+        });
+    hpack.add(&status);
+    chess_window.handle({
+        let game = game.clone();
+        let mut group = group.clone();
+        let mut material = material.clone();
+        let mut status = status.clone();
+        move |w, event| {
+            if let Event::Push = event {
+                let (click_x, click_y) = app::event_coords();
+                let (x, y) = (click_x / CHESS_SIZE, click_y / CHESS_SIZE);
+                dbg!(x, y);
+                // 点击棋盘 画面坐标换回棋盘坐标
+                let pos = game
+                    .borrow()
+                    .view_position(&game::Position { x, y });
+                let last_move = game
+                    .borrow()
+                    .last_move();
+                game.borrow_mut()
+                    .click(&pos);
+                group.clear();
+                w.redraw();
+
+                redrawn(&mut group, &mut material, &game.borrow());
+                // 走完一步后检查是否三次重复局面 只是选子时局面没变 提示留着
+                let moved = game
+                    .borrow()
+                    .last_move()
+                    != last_move;
+                if moved && !show_repetition(&mut status, &game.borrow()) && game.borrow().coach {
+                    // 教练模式 刚走了一步 和走之前局面里最好的着法比较
+                    let worse = game
+                        .borrow_mut()
+                        .is_last_move_worse(COACH_DEPTH);
+                    status.set_label(if worse {
+                        "这步可能有更好的选择"
+                    } else {
+                        ""
+                    });
+                }
+                return true;
+            }
+            return false;
+        }
+    });
     Button::default().with_label("功能");
     hpack.end();
     hpack.auto_layout();